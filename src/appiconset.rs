@@ -0,0 +1,264 @@
+use std::{fs, path::Path};
+
+use crate::{
+    encode::IconFamily,
+    error::IcnsError,
+    formats::IconFormats,
+    iconset::{read_image, write_iconset, ICONSET_FILES},
+    json::Value,
+    IcnsEncoder,
+};
+
+/// # App icon set images
+/// The images of the `mac` idiom of an Xcode `AppIcon.appiconset`:
+/// the size in points, the scale and the icon type they are stored as.
+pub const APPICONSET_IMAGES: [(u32, u32, IconFormats); 10] = [
+    (16, 1, IconFormats::ICP4),
+    (16, 2, IconFormats::IC11),
+    (32, 1, IconFormats::ICP5),
+    (32, 2, IconFormats::IC12),
+    (128, 1, IconFormats::IC07),
+    (128, 2, IconFormats::IC13),
+    (256, 1, IconFormats::IC08),
+    (256, 2, IconFormats::IC14),
+    (512, 1, IconFormats::IC09),
+    (512, 2, IconFormats::IC10),
+];
+
+/// ## Writing an app icon set
+/// Writes an `IconFamily` as the PNG files and the `Contents.json` of
+/// an Xcode `AppIcon.appiconset` directory. The images are written like
+/// `write_iconset` does (with the same file names); sizes without any
+/// image are listed in `Contents.json` without a file name.
+pub fn write_appiconset(family: &IconFamily, path: impl AsRef<Path>) -> Result<(), IcnsError> {
+    let path = path.as_ref();
+    write_iconset(family, path)?;
+
+    let images = APPICONSET_IMAGES
+        .iter()
+        .map(|(size, scale, format)| {
+            let mut image = Vec::new();
+
+            let name = ICONSET_FILES
+                .iter()
+                .find(|(_, other)| other == format)
+                .map(|(name, _)| *name);
+            if let Some(name) = name.filter(|name| path.join(name).is_file()) {
+                image.push(("filename".to_string(), Value::String(name.to_string())));
+            }
+
+            image.push(("idiom".to_string(), Value::String("mac".to_string())));
+            image.push(("scale".to_string(), Value::String(format!("{}x", scale))));
+            image.push(("size".to_string(), Value::String(format!("{0}x{0}", size))));
+
+            Value::Object(image)
+        })
+        .collect();
+
+    let contents = Value::Object(vec![
+        ("images".to_string(), Value::Array(images)),
+        (
+            "info".to_string(),
+            Value::Object(vec![
+                ("author".to_string(), Value::String("xcode".to_string())),
+                ("version".to_string(), Value::Number(1.0)),
+            ]),
+        ),
+    ]);
+
+    fs::write(path.join("Contents.json"), contents.to_json())?;
+
+    Ok(())
+}
+
+/// ## Reading an app icon set
+/// Reads the `mac` images listed in the `Contents.json` of an Xcode
+/// `AppIcon.appiconset` directory into an `IconFamily`. Images of other
+/// idioms, sizes without a file and unknown sizes are skipped.
+/// Files that are not PNGs are re-encoded as PNG.
+pub fn read_appiconset(path: impl AsRef<Path>) -> Result<IconFamily, IcnsError> {
+    let path = path.as_ref();
+    let contents = Value::parse(&fs::read_to_string(path.join("Contents.json"))?)?;
+
+    let images = contents
+        .get("images")
+        .and_then(|images| images.as_array())
+        .ok_or_else(|| IcnsError::InvalidJson("missing \"images\" array".to_string()))?;
+
+    let mut family = IconFamily::new();
+
+    for image in images {
+        let field = |key| image.get(key).and_then(|value| value.as_str());
+
+        if field("idiom") != Some("mac") {
+            continue;
+        }
+        let name = match field("filename") {
+            Some(name) => name,
+            None => continue,
+        };
+
+        // The file must be inside the directory
+        if Path::new(name).file_name() != Some(name.as_ref()) {
+            return Err(IcnsError::InvalidJson(format!(
+                "invalid filename \"{}\"",
+                name
+            )));
+        }
+
+        // e.g. "16x16" and "2x"
+        let size = field("size")
+            .and_then(|size| size.split_once('x'))
+            .filter(|(width, height)| width == height)
+            .and_then(|(width, _)| width.parse::<u32>().ok());
+        let scale = field("scale")
+            .and_then(|scale| scale.strip_suffix('x'))
+            .and_then(|scale| scale.parse::<u32>().ok());
+
+        let format = APPICONSET_IMAGES
+            .iter()
+            .find(|(other_size, other_scale, _)| {
+                Some(*other_size) == size && Some(*other_scale) == scale
+            })
+            .map(|(_, _, format)| *format);
+
+        if let Some(format) = format {
+            if family.get(format).is_none() {
+                family.add_data(read_image(&path.join(name), format)?);
+            }
+        }
+    }
+
+    Ok(family)
+}
+
+impl IcnsEncoder {
+    /// ## Writing an app icon set
+    /// Encodes the image in every size of an Xcode `AppIcon.appiconset`
+    /// and writes the directory with its `Contents.json`.
+    /// The configured formats are not used.
+    ///
+    /// ```no_run
+    /// use icns_rs::IcnsEncoder;
+    ///
+    /// let mut encoder = IcnsEncoder::new();
+    /// encoder.data(image::open("icon.png").unwrap());
+    ///
+    /// encoder
+    ///     .write_appiconset("Assets.xcassets/AppIcon.appiconset")
+    ///     .unwrap();
+    /// ```
+    pub fn write_appiconset(&self, path: impl AsRef<Path>) -> Result<(), IcnsError> {
+        let formats = APPICONSET_IMAGES.map(|(_, _, format)| format);
+
+        write_appiconset(&self.family(&self.data, &formats)?, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("icns-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+
+        path
+    }
+
+    #[test]
+    fn appiconset_round_trip() {
+        let path = temp_dir("appiconset").join("AppIcon.appiconset");
+
+        let mut encoder = IcnsEncoder::new();
+        encoder.data(DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            64,
+            64,
+            Rgba([0x10, 0x20, 0x30, 0x40]),
+        )));
+        encoder.write_appiconset(&path).unwrap();
+
+        let contents =
+            Value::parse(&fs::read_to_string(path.join("Contents.json")).unwrap()).unwrap();
+        let images = contents.get("images").unwrap().as_array().unwrap();
+        assert_eq!(images.len(), 10);
+        assert_eq!(
+            images[1],
+            Value::Object(vec![
+                (
+                    "filename".to_string(),
+                    Value::String("icon_16x16@2x.png".to_string())
+                ),
+                ("idiom".to_string(), Value::String("mac".to_string())),
+                ("scale".to_string(), Value::String("2x".to_string())),
+                ("size".to_string(), Value::String("16x16".to_string())),
+            ])
+        );
+
+        let family = read_appiconset(&path).unwrap();
+        assert_eq!(family.data.len(), 10);
+
+        let image = family.image(IconFormats::IC10).unwrap();
+        assert_eq!(image.dimensions(), (1024, 1024));
+        assert_eq!(image.to_rgba8().get_pixel(0, 0).0, [0x10, 0x20, 0x30, 0x40]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn read_appiconset_idioms() {
+        let path = temp_dir("appiconset-idioms");
+        fs::create_dir_all(&path).unwrap();
+
+        DynamicImage::new_rgba8(32, 32)
+            .save(path.join("a.png"))
+            .unwrap();
+        fs::write(
+            path.join("Contents.json"),
+            r#"{
+                "images": [
+                    {"filename": "a.png", "idiom": "iphone", "scale": "2x", "size": "16x16"},
+                    {"filename": "a.png", "idiom": "mac", "scale": "2x", "size": "16x16"},
+                    {"idiom": "mac", "scale": "1x", "size": "32x32"},
+                    {"filename": "a.png", "idiom": "mac", "scale": "1x", "size": "20x20"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let family = read_appiconset(&path).unwrap();
+        assert_eq!(family.data.len(), 1);
+        assert!(family.get(IconFormats::IC11).is_some());
+
+        // The image must have the size of its slot
+        fs::write(
+            path.join("Contents.json"),
+            r#"{"images": [{"filename": "a.png", "idiom": "mac", "scale": "1x", "size": "16x16"}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            read_appiconset(&path),
+            Err(IcnsError::InvalidDimensions { .. })
+        ));
+
+        // Files outside the directory are rejected
+        for name in ["../a.png", "sub/a.png", ".."] {
+            fs::write(
+                path.join("Contents.json"),
+                format!(
+                    r#"{{"images": [{{"filename": "{}", "idiom": "mac", "scale": "2x", "size": "16x16"}}]}}"#,
+                    name
+                ),
+            )
+            .unwrap();
+            assert!(matches!(
+                read_appiconset(&path),
+                Err(IcnsError::InvalidJson(_))
+            ));
+        }
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use icns_rs::{
+    appiconset::{read_appiconset, write_appiconset},
+    encode::IconFamily,
+    formats::FileFormat,
+    ico::read_ico,
+    iconset::{read_iconset, write_iconset},
+    validate::validate_bytes,
+    IcnsEncoder, IconFormats,
+};
+use image::imageops::FilterType;
+
+/// Create, inspect and extract Apple Icon Image (.icns) files
+#[derive(Parser)]
+#[command(name = "icns", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create an icns file from one or more images
+    Create {
+        /// The source images. Every format uses the image with its exact
+        /// size, or the closest larger image if there is none
+        #[arg(required = true)]
+        images: Vec<PathBuf>,
+        /// The output file
+        #[arg(short, long)]
+        output: PathBuf,
+        /// The OSTypes to encode (e.g. is32,s8mk,ic10),
+        /// `recommended` or `all`
+        #[arg(short, long, value_delimiter = ',', default_value = "recommended")]
+        formats: Vec<String>,
+        /// The filter used to resize the images
+        #[arg(long, value_enum, default_value_t = Filter::Nearest)]
+        filter: Filter,
+        /// An image for the dark appearance
+        #[arg(long)]
+        dark: Option<PathBuf>,
+    },
+    /// List the entries of an icns file
+    Info {
+        /// The icns file
+        input: PathBuf,
+    },
+    /// Extract every image of an icns file as PNG
+    Extract {
+        /// The icns file
+        input: PathBuf,
+        /// The output directory
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Check icns files for malformed entries. Fails if any problem is found
+    Validate {
+        /// The icns files
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Convert an .iconset or .appiconset directory or .ico file
+    /// to an icns file or the other way around
+    Convert {
+        /// The .iconset or .appiconset directory, .ico file or icns file
+        input: PathBuf,
+        /// The icns file, .iconset or .appiconset directory or .ico file
+        output: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Filter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<Filter> for FilterType {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Gaussian => FilterType::Gaussian,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Create {
+            images,
+            output,
+            formats,
+            filter,
+            dark,
+        } => create(&images, &output, &formats, filter.into(), dark.as_deref()),
+        Command::Info { input } => info(&input),
+        Command::Extract { input, output } => extract(&input, &output),
+        Command::Validate { inputs } => validate(&inputs),
+        Command::Convert { input, output } => convert(&input, &output),
+    };
+
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Returns the OSType of a format as text (e.g. "is32")
+fn os_type_name(os_type: [u8; 4]) -> String {
+    os_type
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                (byte as char).to_string()
+            } else {
+                format!("\\x{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+fn parse_formats(names: &[String]) -> Result<Vec<IconFormats>, Box<dyn Error>> {
+    let mut formats = Vec::new();
+
+    for name in names {
+        match name.as_str() {
+            "recommended" => formats.extend(IconFormats::recommended()),
+            "all" => formats.extend(IconFormats::all()),
+            _ => {
+                let format = <[u8; 4]>::try_from(name.as_bytes())
+                    .ok()
+                    .and_then(IconFormats::from_bytes)
+                    .ok_or_else(|| format!("Unknown format \"{}\"", name))?;
+
+                formats.push(format);
+            }
+        }
+    }
+
+    Ok(formats)
+}
+
+fn create(
+    images: &[PathBuf],
+    output: &Path,
+    formats: &[String],
+    filter: FilterType,
+    dark: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let formats = parse_formats(formats)?;
+    let images = images
+        .iter()
+        .map(image::open)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut encoder = IcnsEncoder::new();
+    encoder.formats(formats).filter(filter);
+
+    for image in images {
+        encoder.add_data(image);
+    }
+    if let Some(dark) = dark {
+        encoder.dark_data(Some(image::open(dark)?));
+    }
+
+    let mut file = BufWriter::new(File::create(output)?);
+    encoder.write_to(&mut file)?;
+    file.flush()?;
+
+    Ok(())
+}
+
+fn info(input: &Path) -> Result<(), Box<dyn Error>> {
+    let family = IconFamily::parse(&fs::read(input)?)?;
+
+    print_family(&family, "");
+
+    if let Some(version) = family.version()? {
+        println!("Version: {}", version);
+    }
+    if let Some(name) = family.name() {
+        println!("Name: {}", name);
+    }
+    if let Some(info) = family.info()? {
+        for (key, value) in info {
+            println!("Info: {} = {:?}", key, value);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_family(family: &IconFamily, indent: &str) {
+    println!(
+        "{}{:<12} {:>10}  {:<10} {:<22} Compression",
+        indent, "OSType", "Bytes", "Size", "Type"
+    );
+
+    for entry in &family.data {
+        let (size, kind, compression) = match IconFormats::from_bytes(entry.os_type) {
+            Some(format) => {
+                let (width, height) = format.get_dimensions();
+                let (kind, compression) = describe(format.get_format(), &entry.data);
+
+                (format!("{}x{}", width, height), kind, compression)
+            }
+            None => ("-".to_string(), "Unknown", "-"),
+        };
+
+        println!(
+            "{}{:<12} {:>10}  {:<10} {:<22} {}",
+            indent,
+            os_type_name(entry.os_type),
+            entry.data.len(),
+            size,
+            kind,
+            compression
+        );
+    }
+
+    if let Some(dark) = &family.dark {
+        println!("{}Dark appearance:", indent);
+        print_family(dark, &format!("{}  ", indent));
+    }
+}
+
+/// Describes the type and compression of an entry
+fn describe(format: FileFormat, data: &[u8]) -> (&'static str, &'static str) {
+    // The ARGB and PNG types can contain other data
+    if data.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
+        return ("PNG", "PNG");
+    }
+    if data.starts_with(&[0x00, 0x00, 0x00, 0x0C, 0x6A, 0x50]) || data.starts_with(&[0xFF, 0x4F]) {
+        return ("JPEG 2000", "JPEG 2000");
+    }
+
+    match format {
+        FileFormat::RGB => ("24-bit RGB", "PackBits"),
+        FileFormat::ARGB => ("32-bit ARGB", "PackBits"),
+        FileFormat::MASK => ("8-bit mask", "None"),
+        FileFormat::PNG => ("PNG", "PNG"),
+        FileFormat::MONO => ("1-bit mono + mask", "None"),
+        FileFormat::PALETTE4 => ("4-bit palette", "None"),
+        FileFormat::PALETTE8 => ("8-bit palette", "None"),
+    }
+}
+
+fn extract(input: &Path, output: &Path) -> Result<(), Box<dyn Error>> {
+    let family = IconFamily::parse(&fs::read(input)?)?;
+    fs::create_dir_all(output)?;
+
+    extract_family(&family, output, "")?;
+    if let Some(dark) = &family.dark {
+        extract_family(dark, output, "-dark")?;
+    }
+
+    Ok(())
+}
+
+fn extract_family(family: &IconFamily, output: &Path, suffix: &str) -> Result<(), Box<dyn Error>> {
+    for entry in &family.data {
+        let format = match IconFormats::from_bytes(entry.os_type) {
+            Some(format) if format.get_format() != FileFormat::MASK => format,
+            _ => continue,
+        };
+
+        // Skip the entries that can't be decoded (e.g. JPEG 2000)
+        let image = match family.image(format) {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", os_type_name(entry.os_type), e);
+                continue;
+            }
+        };
+
+        let path = output.join(format!(
+            "{}{}.png",
+            os_type_name(entry.os_type).replace('#', "_"),
+            suffix
+        ));
+
+        image.save(&path)?;
+        println!("{}", path.display());
+    }
+
+    Ok(())
+}
+
+fn validate(inputs: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut invalid = 0;
+
+    for input in inputs {
+        let diagnostics = match validate_bytes(&fs::read(input)?) {
+            Ok(diagnostics) => diagnostics.iter().map(|d| d.to_string()).collect(),
+            Err(e) => vec![e.to_string()],
+        };
+
+        if !diagnostics.is_empty() {
+            invalid += 1;
+        }
+        for diagnostic in diagnostics {
+            println!("{}: {}", input.display(), diagnostic);
+        }
+    }
+
+    if invalid > 0 {
+        return Err(format!("{} of {} files are invalid", invalid, inputs.len()).into());
+    }
+
+    Ok(())
+}
+
+fn convert(input: &Path, output: &Path) -> Result<(), Box<dyn Error>> {
+    if input.is_dir() {
+        let family = if has_extension(input, "appiconset") {
+            read_appiconset(input)?
+        } else {
+            read_iconset(input)?
+        };
+        if family.data.is_empty() {
+            return Err(format!("{} contains no icon images", input.display()).into());
+        }
+
+        let mut file = BufWriter::new(File::create(output)?);
+        family.write_to(&mut file)?;
+        file.flush()?;
+    } else if has_extension(input, "ico") {
+        let mut encoder = IcnsEncoder::new();
+        encoder.formats(IconFormats::recommended());
+        for image in read_ico(&fs::read(input)?)? {
+            encoder.add_data(image);
+        }
+
+        let mut file = BufWriter::new(File::create(output)?);
+        encoder.write_to(&mut file)?;
+        file.flush()?;
+    } else {
+        let family = IconFamily::parse(&fs::read(input)?)?;
+
+        if has_extension(output, "ico") {
+            fs::write(output, family.to_ico()?)?;
+        } else if has_extension(output, "appiconset") {
+            write_appiconset(&family, output)?;
+        } else {
+            write_iconset(&family, output)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
@@ -1,17 +1,53 @@
-use std::io::Write;
-
-use crate::{encode::IcnsDataEntry, formats::IconFormats, packbits};
-
-use image::{codecs::png::PngEncoder, imageops::FilterType, DynamicImage, ImageEncoder};
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::{
+    encode::IcnsDataEntry,
+    error::IcnsError,
+    formats::{FileFormat, IconFormats},
+    packbits::{self, Compression},
+    palette,
+};
+
+use image::{
+    codecs::png::PngEncoder,
+    imageops::{self, FilterType},
+    DynamicImage, GenericImageView, ImageEncoder,
+};
+
+/// The key of a resized image: the width, height and filter
+type ResizeKey = (u32, u32, u8);
 
 /// The ImageBuilder struct
 /// This struct is used to build the image data, specifically,
 /// resizing the image and encoding it as a RGB, ARGB, mask,
-/// or PNG image
+/// PNG, 1-bit mono, or palette image
+///
+/// ## Resize cache
+/// The resized images are cached by size and filter, so building
+/// formats of the same size (e.g. is32, s8mk and ic04) from one
+/// builder only resizes the image once. The cache is cleared
+/// by `.data()`.
+///
+/// ## Image data
+/// The `data` field is private so the cache can't go stale. Set the
+/// image with `.data()` and read it back with `.get_data()`.
 pub struct ImageBuilder {
     pub format: IconFormats,
-    pub data: DynamicImage,
+    data: DynamicImage,
     pub filter: FilterType,
+    pub dither: bool,
+    pub compression: Compression,
+    cache: Mutex<HashMap<ResizeKey, Arc<OnceLock<Arc<DynamicImage>>>>>,
+}
+
+impl Default for ImageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ImageBuilder {
@@ -20,6 +56,9 @@ impl ImageBuilder {
             format: IconFormats::IS32,
             data: DynamicImage::new_rgb8(1, 1),
             filter: FilterType::Nearest,
+            dither: false,
+            compression: Compression::Fast,
+            cache: Mutex::new(HashMap::new()),
         }
     }
 
@@ -34,10 +73,17 @@ impl ImageBuilder {
     /// Sets the image data. Encode a png and pass it as a DynamicImage.
     pub fn data(&mut self, data: DynamicImage) -> &mut Self {
         self.data = data;
+        self.cache.get_mut().unwrap().clear();
 
         self
     }
 
+    /// Returns the image data
+    /// The data is only set through `.data()`, which clears the resize cache
+    pub fn get_data(&self) -> &DynamicImage {
+        &self.data
+    }
+
     /// Sets the filter type to be used when resizing the image
     /// - `Nearest`: Nearest neighbor interpolation
     /// - `Triangle`: Triangle interpolation
@@ -51,48 +97,90 @@ impl ImageBuilder {
         self
     }
 
+    /// Sets whether the colors should be dithered when reducing the
+    /// image to the few colors of the classic icon types
+    /// - `false`: Every pixel is mapped to the closest color (threshold)
+    /// - `true`: Floyd–Steinberg dithering
+    ///
+    /// The default is `false`
+    pub fn dither(&mut self, dither: bool) -> &mut Self {
+        self.dither = dither;
+
+        self
+    }
+
+    /// Sets the PackBits compression of the RGB and ARGB images
+    /// - `Fast`: Greedy compression
+    /// - `Optimal`: The smallest output, but slower
+    ///
+    /// The default is `Fast`
+    pub fn compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = compression;
+
+        self
+    }
+
+    /// Resizes the image to the dimensions of the format
+    /// The result is cached, see the resize cache above
+    fn resized(&self) -> Arc<DynamicImage> {
+        self.resized_to(self.format)
+    }
+
+    /// Resizes the image to the dimensions of any format
+    /// Unlike `.format()` this doesn't need a mutable builder,
+    /// so one builder can be shared between threads
+    pub(crate) fn resized_to(&self, format: IconFormats) -> Arc<DynamicImage> {
+        let (width, height) = format.get_dimensions();
+
+        self.resized_to_dimensions(width as u32, height as u32)
+    }
+
+    /// Resizes the image to any dimensions (e.g. for ICO files)
+    /// The result always has exactly the dimensions. For square
+    /// dimensions the aspect ratio of the source is kept and a
+    /// non-square source is centered on a transparent background.
+    pub(crate) fn resized_to_dimensions(&self, width: u32, height: u32) -> Arc<DynamicImage> {
+        let key = (width, height, filter_key(self.filter));
+
+        // The cache is only locked to get the slot of the size, so
+        // formats of the same size that are encoded in parallel wait
+        // for the same resize instead of resizing again
+        let slot = self.cache.lock().unwrap().entry(key).or_default().clone();
+
+        slot.get_or_init(|| {
+            Arc::new(if width == height {
+                letterbox(self.data.resize(width, height, self.filter), width, height)
+            } else {
+                self.data.resize_exact(width, height, self.filter)
+            })
+        })
+        .clone()
+    }
+
     /// Encodes an image as a RGB image
     /// You probably want to use `.build()` instead of this method
-    pub fn rgb_image(&self) -> Result<Box<[u8]>, String> {
-        let size = self.format.get_size() as u32;
-        let resized = self.data.resize(size, size, self.filter);
+    pub fn rgb_image(&self) -> Result<Box<[u8]>, IcnsError> {
+        self.rgb_data(self.format, &self.resized())
+    }
+
+    /// Encodes an already resized image, see `.rgb_image()`
+    fn rgb_data(
+        &self,
+        format: IconFormats,
+        resized: &DynamicImage,
+    ) -> Result<Box<[u8]>, IcnsError> {
         let rgb8 = resized.to_rgb8();
-        let data = rgb8.pixels().collect::<Vec<_>>();
 
-        let channels = [
-            // Offset if the type is it32
-            if self.format == IconFormats::IT32 {
-                vec![0x00, 0x00, 0x00, 0x00].into_boxed_slice()
-            } else {
-                Vec::new().into_boxed_slice()
-            },
-            // Red channel
-            packbits::compress(
-                data.iter()
-                    .map(|pixel| pixel[0])
-                    .collect::<Vec<_>>()
-                    .into_boxed_slice(),
-            ),
-            // Green channel
-            packbits::compress(
-                data.iter()
-                    .map(|pixel| pixel[1])
-                    .collect::<Vec<_>>()
-                    .into_boxed_slice(),
-            ),
-            // Blue channel
-            packbits::compress(
-                data.iter()
-                    .map(|pixel| pixel[2])
-                    .collect::<Vec<_>>()
-                    .into_boxed_slice(),
-            ),
-        ];
-
-        let mut buffer = Vec::with_capacity(channels.iter().map(|c| c.len()).sum::<usize>());
-
-        for b in channels {
-            buffer.extend_from_slice(&b);
+        let mut buffer = Vec::with_capacity(rgb8.len() + 4);
+
+        // Offset if the type is it32
+        if format == IconFormats::IT32 {
+            buffer.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+        }
+
+        // Red, green and blue channels
+        for channel in 0..3 {
+            self.compress_channel(rgb8.pixels().map(|pixel| pixel[channel]), &mut buffer);
         }
 
         Ok(buffer.into_boxed_slice())
@@ -100,59 +188,46 @@ impl ImageBuilder {
 
     /// Encodes an image as a ARGB
     /// You probably want to use `.build()` instead of this method
-    pub fn argb_image(&self) -> Result<Box<[u8]>, String> {
-        let size = self.format.get_size() as u32;
-        let resized = self.data.resize(size, size, self.filter);
+    pub fn argb_image(&self) -> Result<Box<[u8]>, IcnsError> {
+        self.argb_data(&self.resized())
+    }
+
+    /// Encodes an already resized image, see `.argb_image()`
+    fn argb_data(&self, resized: &DynamicImage) -> Result<Box<[u8]>, IcnsError> {
         let rgba8 = resized.to_rgba8();
-        let data = rgba8.pixels().collect::<Vec<_>>();
-
-        let channels = [
-            // File header
-            vec![0x41, 0x52, 0x47, 0x42].into_boxed_slice(), // ARGB
-            // Alpha channel
-            packbits::compress(
-                data.iter()
-                    .map(|pixel| pixel[3])
-                    .collect::<Vec<_>>()
-                    .into_boxed_slice(),
-            ),
-            // Red channel
-            packbits::compress(
-                data.iter()
-                    .map(|pixel| pixel[0])
-                    .collect::<Vec<_>>()
-                    .into_boxed_slice(),
-            ),
-            // Green channel
-            packbits::compress(
-                data.iter()
-                    .map(|pixel| pixel[1])
-                    .collect::<Vec<_>>()
-                    .into_boxed_slice(),
-            ),
-            // Blue channel
-            packbits::compress(
-                data.iter()
-                    .map(|pixel| pixel[2])
-                    .collect::<Vec<_>>()
-                    .into_boxed_slice(),
-            ),
-        ];
-
-        let mut buffer = Vec::with_capacity(channels.iter().map(|c| c.len()).sum::<usize>());
-
-        for b in channels {
-            buffer.extend_from_slice(&b);
+
+        let mut buffer = Vec::with_capacity(rgba8.len() + 4);
+
+        // File header
+        buffer.extend_from_slice(&[0x41, 0x52, 0x47, 0x42]); // ARGB
+
+        // Alpha, red, green and blue channels
+        for channel in [3, 0, 1, 2] {
+            self.compress_channel(rgba8.pixels().map(|pixel| pixel[channel]), &mut buffer);
         }
 
         Ok(buffer.into_boxed_slice())
     }
 
+    /// Compresses a channel and appends it to the buffer
+    fn compress_channel(&self, channel: impl Iterator<Item = u8>, buffer: &mut Vec<u8>) {
+        match self.compression {
+            Compression::Fast => packbits::compress_iter(channel, buffer),
+            // The optimal encoder needs the whole channel
+            Compression::Optimal => {
+                packbits::compress_optimal_into(&channel.collect::<Vec<_>>(), buffer)
+            }
+        }
+    }
+
     /// Encodes an image as a mask
     /// You probably want to use `.build()` instead of this method
-    pub fn mask_image(&self) -> Result<Box<[u8]>, String> {
-        let size = self.format.get_size() as u32;
-        let resized = self.data.resize(size, size, self.filter);
+    pub fn mask_image(&self) -> Result<Box<[u8]>, IcnsError> {
+        self.mask_data(&self.resized())
+    }
+
+    /// Encodes an already resized image, see `.mask_image()`
+    fn mask_data(&self, resized: &DynamicImage) -> Result<Box<[u8]>, IcnsError> {
         let luma = resized.to_luma_alpha8();
         let data = luma.pixels().collect::<Vec<_>>();
 
@@ -166,10 +241,127 @@ impl ImageBuilder {
         Ok(mask)
     }
 
+    /// Encodes an image as a 1-bit mono icon followed by a 1-bit mask
+    /// Dark pixels are black and pixels that are at least half opaque
+    /// are part of the mask. See `.dither()` for the conversion.
+    /// You probably want to use `.build()` instead of this method
+    pub fn mono_image(&self) -> Result<Box<[u8]>, IcnsError> {
+        self.mono_data(self.format, &self.resized())
+    }
+
+    /// Encodes an already resized image, see `.mono_image()`
+    fn mono_data(
+        &self,
+        format: IconFormats,
+        resized: &DynamicImage,
+    ) -> Result<Box<[u8]>, IcnsError> {
+        let (width, height) = format.get_dimensions();
+        let luma = resized.to_luma_alpha8();
+
+        // The pixels in the order of the format, the resized image
+        // has the same dimensions
+        let pixels = (0..height as u32)
+            .flat_map(|y| (0..width as u32).map(move |x| (x, y)))
+            .map(|(x, y)| luma.get_pixel(x, y))
+            .collect::<Vec<_>>();
+
+        let mut levels = pixels
+            .iter()
+            .map(|pixel| pixel[0] as i16)
+            .collect::<Vec<_>>();
+        let mut icon = vec![0x00; width * height / 8];
+        let mut mask = vec![0x00; width * height / 8];
+
+        for (i, pixel) in pixels.iter().enumerate() {
+            let opaque = pixel[1] >= 0x80;
+            let black = levels[i] < 0x80;
+
+            // Transparent pixels are left white
+            if opaque && black {
+                icon[i / 8] |= 0x80 >> (i % 8);
+            }
+            if opaque {
+                mask[i / 8] |= 0x80 >> (i % 8);
+            }
+
+            // Spread the error to the neighbouring pixels
+            if self.dither {
+                let error = levels[i] - if black { 0x00 } else { 0xFF };
+                let (x, y) = (i % width, i / width);
+
+                if x + 1 < width {
+                    levels[i + 1] += error * 7 / 16;
+                }
+                if y + 1 < height {
+                    if x > 0 {
+                        levels[i + width - 1] += error * 3 / 16;
+                    }
+                    levels[i + width] += error * 5 / 16;
+                    if x + 1 < width {
+                        levels[i + width + 1] += error / 16;
+                    }
+                }
+            }
+        }
+
+        icon.extend_from_slice(&mask);
+
+        Ok(icon.into_boxed_slice())
+    }
+
+    /// Encodes an image as a 4-bit or 8-bit palette image
+    /// The colors are mapped to the Mac OS system palettes.
+    /// See `.dither()` for the conversion.
+    /// You probably want to use `.build()` instead of this method
+    pub fn palette_image(&self) -> Result<Box<[u8]>, IcnsError> {
+        self.palette_data(self.format, &self.resized())
+    }
+
+    /// Encodes an already resized image, see `.palette_image()`
+    fn palette_data(
+        &self,
+        format: IconFormats,
+        resized: &DynamicImage,
+    ) -> Result<Box<[u8]>, IcnsError> {
+        let (width, _) = format.get_dimensions();
+        let rgba8 = resized.to_rgba8();
+
+        // Transparent pixels are left white (index 0)
+        let colors = rgba8
+            .pixels()
+            .map(|pixel| {
+                if pixel[3] >= 0x80 {
+                    [pixel[0], pixel[1], pixel[2]]
+                } else {
+                    [0xFF, 0xFF, 0xFF]
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if format.get_format() == FileFormat::PALETTE4 {
+            let indices = palette::quantize(&colors, width, &palette::MAC_16, self.dither);
+
+            // Two pixels per byte (high nibble first)
+            return Ok(indices
+                .chunks(2)
+                .map(|pair| pair[0] << 4 | pair.get(1).unwrap_or(&0))
+                .collect::<Vec<_>>()
+                .into_boxed_slice());
+        }
+
+        let indices = palette::quantize(&colors, width, &palette::MAC_256, self.dither);
+
+        Ok(indices.into_boxed_slice())
+    }
+
     /// Encodes an image as a PNG
-    pub fn png_image(&self) -> Result<Box<[u8]>, String> {
-        let size = self.format.get_size() as u32;
-        let data = self.data.resize(size, size, self.filter);
+    pub fn png_image(&self) -> Result<Box<[u8]>, IcnsError> {
+        self.png_data(&self.resized())
+    }
+
+    /// Encodes an already resized image, see `.png_image()`
+    fn png_data(&self, resized: &DynamicImage) -> Result<Box<[u8]>, IcnsError> {
+        let (width, height) = resized.dimensions();
 
         let mut buffer = Vec::new();
 
@@ -192,24 +384,216 @@ impl ImageBuilder {
             buffer: &mut buffer,
         });
 
-        let color = data.color();
+        let color = resized.color();
 
-        let result = encoder.write_image(data.into_bytes().as_slice(), size, size, color);
+        encoder.write_image(resized.as_bytes(), width, height, color)?;
 
-        match result {
-            Ok(_) => Ok(buffer.into_boxed_slice()),
-            Err(e) => Err(format!("Failed to encode PNG: {}", e)),
-        }
+        Ok(buffer.into_boxed_slice())
     }
 
-    pub fn build(&self) -> Result<IcnsDataEntry, String> {
-        let data = match self.format.get_format() {
-            crate::formats::FileFormat::RGB => self.rgb_image(),
-            crate::formats::FileFormat::ARGB => self.argb_image(),
-            crate::formats::FileFormat::MASK => self.mask_image(),
-            crate::formats::FileFormat::PNG => self.png_image(),
+    pub fn build(&self) -> Result<IcnsDataEntry, IcnsError> {
+        self.build_resized(self.format, &self.resized())
+    }
+
+    /// Encodes an image that already has the dimensions of the format
+    /// (e.g. from `.resized_to()` of a shared builder) without copying
+    /// or resizing it again. The format and data of the builder are ignored.
+    pub(crate) fn build_resized(
+        &self,
+        format: IconFormats,
+        resized: &DynamicImage,
+    ) -> Result<IcnsDataEntry, IcnsError> {
+        let data = match format.get_format() {
+            FileFormat::RGB => self.rgb_data(format, resized),
+            FileFormat::ARGB => self.argb_data(resized),
+            FileFormat::MASK => self.mask_data(resized),
+            FileFormat::PNG => self.png_data(resized),
+            FileFormat::MONO => self.mono_data(format, resized),
+            FileFormat::PALETTE4 => self.palette_data(format, resized),
+            FileFormat::PALETTE8 => self.palette_data(format, resized),
         }?;
 
-        Ok(IcnsDataEntry::new(self.format.get_bytes(), data))
+        Ok(IcnsDataEntry::new(format.get_bytes(), data))
+    }
+}
+
+/// Encodes any image as an RGBA PNG
+pub(crate) fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, IcnsError> {
+    let rgba8 = image.to_rgba8();
+    let mut buffer = Vec::new();
+
+    PngEncoder::new(&mut buffer).write_image(
+        &rgba8,
+        rgba8.width(),
+        rgba8.height(),
+        image::ColorType::Rgba8,
+    )?;
+
+    Ok(buffer)
+}
+
+/// Centers an image on a transparent background of the dimensions
+fn letterbox(image: DynamicImage, width: u32, height: u32) -> DynamicImage {
+    if image.dimensions() == (width, height) {
+        return image;
+    }
+
+    let mut background = DynamicImage::new_rgba8(width, height);
+    imageops::replace(
+        &mut background,
+        &image,
+        ((width - image.width()) / 2) as i64,
+        ((height - image.height()) / 2) as i64,
+    );
+
+    background
+}
+
+/// Maps a filter to a key of the resize cache
+/// (`FilterType` doesn't implement `Hash`)
+fn filter_key(filter: FilterType) -> u8 {
+    match filter {
+        FilterType::Nearest => 0,
+        FilterType::Triangle => 1,
+        FilterType::CatmullRom => 2,
+        FilterType::Gaussian => 3,
+        FilterType::Lanczos3 => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_cache() {
+        let mut builder = ImageBuilder::new();
+        builder.data(DynamicImage::new_rgba8(64, 64));
+
+        for format in [IconFormats::IS32, IconFormats::S8MK, IconFormats::IC04] {
+            builder.format(format).build().unwrap();
+        }
+        assert_eq!(builder.cache.lock().unwrap().len(), 1);
+
+        // A different filter is resized again
+        builder.filter(FilterType::Triangle).build().unwrap();
+        builder.format(IconFormats::IL32).build().unwrap();
+        assert_eq!(builder.cache.lock().unwrap().len(), 3);
+
+        builder.data(DynamicImage::new_rgba8(32, 32));
+        assert!(builder.cache.lock().unwrap().is_empty());
+        // Threads resizing to the same size share one resize
+        let resized = std::thread::scope(|scope| {
+            let threads = (0..4)
+                .map(|_| scope.spawn(|| builder.resized_to(IconFormats::IC07)))
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert!(resized.iter().all(|image| Arc::ptr_eq(image, &resized[0])));
+    }
+
+    #[test]
+    fn non_square_source() {
+        // Red on the left half, green on the right
+        let source = image::RgbaImage::from_fn(64, 32, |x, _| {
+            if x < 32 {
+                image::Rgba([0xFF, 0x00, 0x00, 0xFF])
+            } else {
+                image::Rgba([0x00, 0xFF, 0x00, 0xFF])
+            }
+        });
+
+        let mut builder = ImageBuilder::new();
+        builder.data(DynamicImage::ImageRgba8(source));
+
+        let formats = IconFormats::all()
+            .into_iter()
+            .filter(|format| format.get_size() <= 128)
+            .collect::<Vec<_>>();
+        for format in formats {
+            let entry = builder.format(format).build().unwrap();
+            let (width, height) = format.get_dimensions();
+
+            if format.get_format() == FileFormat::MASK {
+                assert_eq!(entry.data.len(), width * height, "{:?}", format);
+                continue;
+            }
+
+            let image = crate::parser::ImageParser::new()
+                .format(format)
+                .data(entry)
+                .parse()
+                .unwrap();
+            assert_eq!(
+                image.dimensions(),
+                (width as u32, height as u32),
+                "{:?}",
+                format
+            );
+        }
+
+        // The source is centered, the top and bottom are transparent
+        let image = builder.resized_to(IconFormats::IC11);
+        assert_eq!(image.dimensions(), (32, 32));
+        assert_eq!(image.get_pixel(0, 0).0, [0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(image.get_pixel(0, 16).0, [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(image.get_pixel(31, 16).0, [0x00, 0xFF, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn dither_non_square_source() {
+        let source = image::RgbaImage::from_fn(64, 32, |x, _| {
+            image::Rgba([(x * 4) as u8, (x * 4) as u8, (x * 4) as u8, 0xFF])
+        });
+
+        let mut builder = ImageBuilder::new();
+        builder.data(DynamicImage::ImageRgba8(source)).dither(true);
+
+        for format in [IconFormats::ICN, IconFormats::ICL4, IconFormats::ICL8] {
+            let entry = builder.format(format).build().unwrap();
+            let image = crate::parser::ImageParser::new()
+                .format(format)
+                .data(entry)
+                .parse()
+                .unwrap();
+
+            assert_eq!(image.dimensions(), (32, 32));
+        }
+    }
+
+    #[test]
+    fn optimal_compression() {
+        // Noise with short runs, which the greedy encoder handles poorly
+        let mut state = 0x2545_F491u32;
+        let image = image::RgbaImage::from_fn(32, 32, |_, _| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let value = if state % 3 == 0 { 0xFF } else { 0x00 };
+
+            image::Rgba([value, value, 0x80, value])
+        });
+
+        let mut builder = ImageBuilder::new();
+        builder
+            .data(DynamicImage::ImageRgba8(image))
+            .format(IconFormats::IC05);
+
+        let fast = builder.build().unwrap();
+        let optimal = builder.compression(Compression::Optimal).build().unwrap();
+        assert!(optimal.data.len() < fast.data.len());
+
+        let decoded = |entry: IcnsDataEntry| {
+            crate::parser::ImageParser::new()
+                .format(IconFormats::IC05)
+                .data(entry)
+                .parse()
+                .unwrap()
+        };
+        assert_eq!(decoded(optimal), decoded(fast));
     }
 }
@@ -0,0 +1,320 @@
+use std::io::{Cursor, Read, Write};
+
+use crate::{
+    error::IcnsError,
+    formats::{FileFormat, IconFormats},
+    reader::IcnsReader,
+    IcnsEncoder,
+};
+
+use image::{
+    error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
+    ColorType, DynamicImage, ImageBuffer, ImageDecoder, ImageEncoder, ImageError, ImageResult,
+    RgbaImage,
+};
+
+/// ## IcnsDecoder
+/// Decodes an ICNS file with the `image` crate's `ImageDecoder` trait.
+/// By default the largest image is used; when there are several images
+/// of that size the best format (PNG, ARGB, RGB, ...) is picked.
+/// Images that can't be decoded (e.g. JPEG 2000) are skipped.
+/// The image is always returned as RGBA.
+///
+/// ```no_run
+/// use icns_rs::codec::IcnsDecoder;
+/// use image::DynamicImage;
+/// use std::{fs::File, io::BufReader};
+///
+/// let file = BufReader::new(File::open("example.icns").unwrap());
+/// let image = DynamicImage::from_decoder(IcnsDecoder::new(file).unwrap()).unwrap();
+/// ```
+pub struct IcnsDecoder {
+    format: IconFormats,
+    image: RgbaImage,
+}
+
+impl IcnsDecoder {
+    /// ## New
+    /// Reads an ICNS file and decodes its largest image.
+    pub fn new(mut reader: impl Read) -> Result<Self, IcnsError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let icns = IcnsReader::new(&data)?;
+
+        let mut formats = icns
+            .entries()
+            .iter()
+            .filter_map(|entry| entry.format())
+            .filter(|format| format.get_format() != FileFormat::MASK)
+            .collect::<Vec<_>>();
+        formats.sort_by_key(|format| {
+            let (width, height) = format.get_dimensions();
+
+            (
+                std::cmp::Reverse(width * height),
+                format.get_format().rank(),
+            )
+        });
+
+        let mut error = None;
+        for format in formats {
+            match icns.image(format) {
+                Ok(image) => {
+                    return Ok(Self {
+                        format,
+                        image: image.to_rgba8(),
+                    })
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+
+        Err(error.unwrap_or(IcnsError::NoImages))
+    }
+
+    /// ## Decoding a format
+    /// Reads an ICNS file and decodes the image of the given format.
+    pub fn with_format(mut reader: impl Read, format: IconFormats) -> Result<Self, IcnsError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let image = IcnsReader::new(&data)?.image(format)?;
+
+        Ok(Self {
+            format,
+            image: image.to_rgba8(),
+        })
+    }
+
+    /// ## Format
+    /// Returns the format of the decoded image.
+    pub fn format(&self) -> IconFormats {
+        self.format
+    }
+}
+
+impl<'a> ImageDecoder<'a> for IcnsDecoder {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        ColorType::Rgba8
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(Cursor::new(self.image.into_raw()))
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        buf.copy_from_slice(&self.image);
+
+        Ok(())
+    }
+}
+
+/// ## IcnsImageEncoder
+/// Encodes an ICNS file with the `image` crate's `ImageEncoder` trait.
+/// The pixels are encoded in every configured format (see `IcnsEncoder`)
+/// and the complete icon family is written to the writer.
+///
+/// ```no_run
+/// use icns_rs::{codec::IcnsImageEncoder, IconFormats};
+/// use image::ImageEncoder;
+/// use std::{fs::File, io::BufWriter};
+///
+/// let image = image::open("icon.png").unwrap().to_rgba8();
+/// let file = BufWriter::new(File::create("icon.icns").unwrap());
+///
+/// IcnsImageEncoder::new_with_formats(file, vec![IconFormats::IC07, IconFormats::IC08])
+///     .write_image(&image, image.width(), image.height(), image::ColorType::Rgba8)
+///     .unwrap();
+/// ```
+pub struct IcnsImageEncoder<W: Write> {
+    writer: W,
+    formats: Vec<IconFormats>,
+}
+
+impl<W: Write> IcnsImageEncoder<W> {
+    /// ## New
+    /// Creates an encoder that writes the recommended formats.
+    /// See `IconFormats::recommended` for more information.
+    pub fn new(writer: W) -> Self {
+        Self::new_with_formats(writer, IconFormats::recommended())
+    }
+
+    /// ## New with formats
+    /// Creates an encoder that writes the given formats.
+    pub fn new_with_formats(writer: W, formats: Vec<IconFormats>) -> Self {
+        Self { writer, formats }
+    }
+}
+
+impl<W: Write> ImageEncoder for IcnsImageEncoder<W> {
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        assert_eq!(
+            width as u64 * height as u64 * color_type.bytes_per_pixel() as u64,
+            buf.len() as u64
+        );
+
+        let image = to_image(buf, width, height, color_type).ok_or_else(|| {
+            ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Name("ICNS".into()),
+                UnsupportedErrorKind::Color(color_type.into()),
+            ))
+        })?;
+
+        let mut encoder = IcnsEncoder::new();
+        encoder.data(image).formats(self.formats);
+        encoder.write_to(&mut self.writer)?;
+
+        Ok(())
+    }
+}
+
+/// Creates an image from native endian pixel data
+fn to_image(buf: &[u8], width: u32, height: u32, color_type: ColorType) -> Option<DynamicImage> {
+    let u16s = || {
+        buf.chunks_exact(2)
+            .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+            .collect::<Vec<_>>()
+    };
+    let f32s = || {
+        buf.chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect::<Vec<_>>()
+    };
+
+    match color_type {
+        ColorType::L8 => {
+            ImageBuffer::from_raw(width, height, buf.to_vec()).map(DynamicImage::ImageLuma8)
+        }
+        ColorType::La8 => {
+            ImageBuffer::from_raw(width, height, buf.to_vec()).map(DynamicImage::ImageLumaA8)
+        }
+        ColorType::Rgb8 => {
+            ImageBuffer::from_raw(width, height, buf.to_vec()).map(DynamicImage::ImageRgb8)
+        }
+        ColorType::Rgba8 => {
+            ImageBuffer::from_raw(width, height, buf.to_vec()).map(DynamicImage::ImageRgba8)
+        }
+        ColorType::L16 => {
+            ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageLuma16)
+        }
+        ColorType::La16 => {
+            ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageLumaA16)
+        }
+        ColorType::Rgb16 => {
+            ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageRgb16)
+        }
+        ColorType::Rgba16 => {
+            ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageRgba16)
+        }
+        ColorType::Rgb32F => {
+            ImageBuffer::from_raw(width, height, f32s()).map(DynamicImage::ImageRgb32F)
+        }
+        ColorType::Rgba32F => {
+            ImageBuffer::from_raw(width, height, f32s()).map(DynamicImage::ImageRgba32F)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode::IconFamily, IcnsEncoder};
+
+    use image::Rgba;
+
+    #[test]
+    fn decode_largest_image() {
+        let mut source = RgbaImage::new(64, 64);
+        source.put_pixel(0, 0, Rgba([0x10, 0x20, 0x30, 0x40]));
+
+        let mut encoder = IcnsEncoder::new();
+        encoder
+            .data(DynamicImage::ImageRgba8(source.clone()))
+            .formats(vec![
+                IconFormats::IS32,
+                IconFormats::IC12,
+                IconFormats::ICP5,
+            ]);
+        let data = encoder.build().unwrap();
+
+        // ic12 (64x64) is the largest
+        let decoder = IcnsDecoder::new(Cursor::new(&data)).unwrap();
+        assert_eq!(decoder.format(), IconFormats::IC12);
+        assert_eq!(decoder.dimensions(), (64, 64));
+
+        let image = DynamicImage::from_decoder(decoder).unwrap();
+        assert_eq!(image.to_rgba8(), source);
+
+        let decoder = IcnsDecoder::with_format(Cursor::new(&data), IconFormats::IS32).unwrap();
+        assert_eq!(decoder.dimensions(), (16, 16));
+        assert!(IcnsDecoder::with_format(Cursor::new(&data), IconFormats::IC10).is_err());
+    }
+
+    #[test]
+    fn decode_without_images() {
+        let data = IconFamily::new().build();
+
+        assert!(matches!(
+            IcnsDecoder::new(Cursor::new(&data)),
+            Err(IcnsError::NoImages)
+        ));
+    }
+
+    /// Writes an image with any `ImageEncoder`
+    fn write_generic(encoder: impl ImageEncoder, image: &DynamicImage) -> ImageResult<()> {
+        encoder.write_image(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color(),
+        )
+    }
+
+    #[test]
+    fn encode_with_image_encoder() {
+        let source = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(
+            32,
+            32,
+            image::Rgb([0xFFFF, 0x8080, 0x0000]),
+        ));
+
+        let mut data = Vec::new();
+        let formats = vec![IconFormats::IS32, IconFormats::S8MK, IconFormats::IC11];
+        write_generic(
+            IcnsImageEncoder::new_with_formats(&mut data, formats),
+            &source,
+        )
+        .unwrap();
+
+        let family = IconFamily::parse(&data).unwrap();
+        assert_eq!(family.data.len(), 3);
+        assert_eq!(
+            family
+                .image(IconFormats::IC11)
+                .unwrap()
+                .to_rgba8()
+                .get_pixel(0, 0)
+                .0,
+            [0xFF, 0x80, 0x00, 0xFF]
+        );
+
+        // The recommended formats by default
+        assert_eq!(
+            IcnsImageEncoder::new(Vec::new()).formats,
+            IconFormats::recommended()
+        );
+    }
+}
@@ -1,4 +1,20 @@
-const MAGIC: [u8; 4] = [0x69, 0x63, 0x6e, 0x73]; // "icns"
+use std::io::{self, Read, Write};
+
+use crate::{
+    error::IcnsError,
+    formats::{FileFormat, IconFormats},
+    parser::ImageParser,
+    plist::{self, Dictionary},
+};
+
+use image::DynamicImage;
+
+pub(crate) const MAGIC: [u8; 4] = [0x69, 0x63, 0x6e, 0x73]; // "icns"
+pub(crate) const TOC: [u8; 4] = [0x54, 0x4F, 0x43, 0x20]; // "TOC "
+pub(crate) const DARK: [u8; 4] = [0xFD, 0xD9, 0x2F, 0xA8]; // Nested dark mode family
+pub(crate) const INFO: [u8; 4] = [0x69, 0x6E, 0x66, 0x6F]; // "info"
+pub(crate) const VERSION: [u8; 4] = [0x69, 0x63, 0x6E, 0x56]; // "icnV"
+pub(crate) const NAME: [u8; 4] = [0x6E, 0x61, 0x6D, 0x65]; // "name"
 
 /// ## IcnsDataEntry
 /// This file contains both the OSType and the data.
@@ -25,6 +41,12 @@ impl IcnsDataEntry {
         (8 + self.data.len()) as u32
     }
 
+    /// ## Is empty
+    /// Returns true if the entry carries no data (only the header).
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     /// ## Building the data
     /// This function compiles the data into a single byte array.
     /// This contains the OSType followed by the length of the data
@@ -33,26 +55,45 @@ impl IcnsDataEntry {
         // Total: 4 bytes (OSType) + 4 bytes (length) + length (data)
         let mut result = Vec::with_capacity(self.len() as usize);
 
-        result.extend_from_slice(&self.os_type);
-        result.extend_from_slice(&(8 + self.data.len() as u32).to_be_bytes());
-        result.extend_from_slice(&self.data);
+        // Writing to a Vec can't fail
+        self.write_to(&mut result).unwrap();
 
         result.into_boxed_slice()
     }
+
+    /// ## Writing the data
+    /// Writes the OSType, the length and the data to a writer
+    /// without copying them into a new buffer first.
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(&self.os_type)?;
+        w.write_all(&self.len().to_be_bytes())?;
+        w.write_all(&self.data)
+    }
 }
 
 /// ## ICNSBuilder
 /// This struct holds a list of data that will be compiled into an ICNS file.
+/// The images for the dark appearance are stored in a nested family.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IconFamily {
     pub data: Vec<IcnsDataEntry>,
+    pub dark: Option<Box<IconFamily>>,
+}
+
+impl Default for IconFamily {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IconFamily {
     /// ## New
     /// Creates a new file format
     pub fn new() -> Self {
-        Self { data: vec![] }
+        Self {
+            data: vec![],
+            dark: None,
+        }
     }
 
     /// ## Adding data
@@ -64,48 +105,384 @@ impl IconFamily {
         self
     }
 
+    /// ## Setting the dark mode family
+    /// Sets the nested family that holds the images for the dark
+    /// appearance. It is stored in an entry with the
+    /// `0xFD 0xD9 0x2F 0xA8` OSType.
+    pub fn set_dark(&mut self, dark: Option<IconFamily>) -> &mut Self {
+        self.dark = dark.map(Box::new);
+
+        self
+    }
+
+    /// ## Replacing data
+    /// Replaces the first entry with the same OSType, or adds the entry
+    /// if there is none.
+    fn replace_data(&mut self, data: IcnsDataEntry) -> &mut Self {
+        match self
+            .data
+            .iter_mut()
+            .find(|entry| entry.os_type == data.os_type)
+        {
+            Some(entry) => *entry = data,
+            None => self.data.push(data),
+        }
+
+        self
+    }
+
+    /// ## Setting the info
+    /// Stores the metadata (e.g. the icon name) as an XML property list
+    /// in the `info` entry. An existing `info` entry is replaced.
+    ///
+    /// ```rust
+    /// use icns_rs::encode::IconFamily;
+    /// use icns_rs::plist::{Dictionary, Value};
+    ///
+    /// let mut info = Dictionary::new();
+    /// info.insert("name".to_string(), Value::String("AppIcon".to_string()));
+    ///
+    /// let mut family = IconFamily::new();
+    /// family.set_info(&info);
+    ///
+    /// assert_eq!(family.info().unwrap(), Some(info));
+    /// ```
+    pub fn set_info(&mut self, info: &Dictionary) -> &mut Self {
+        let xml = plist::Value::Dictionary(info.clone()).to_xml();
+
+        self.replace_data(IcnsDataEntry::new(
+            INFO,
+            xml.into_bytes().into_boxed_slice(),
+        ))
+    }
+
+    /// ## Getting the info
+    /// Parses the XML or binary property list of the `info` entry.
+    /// Returns `None` if the family has no `info` entry.
+    pub fn info(&self) -> Result<Option<Dictionary>, IcnsError> {
+        let entry = match self.data.iter().find(|entry| entry.os_type == INFO) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        match plist::Value::parse(&entry.data)? {
+            plist::Value::Dictionary(info) => Ok(Some(info)),
+            _ => Err(IcnsError::InvalidPlist(
+                "The info entry is not a dictionary".to_string(),
+            )),
+        }
+    }
+
+    /// ## Setting the version
+    /// Stores the version as a 4 byte big endian float in the `icnV`
+    /// entry. Like Apple's iconutil, the entry is placed before the images.
+    pub fn set_version(&mut self, version: f32) -> &mut Self {
+        let entry = IcnsDataEntry::new(VERSION, Box::new(version.to_be_bytes()));
+
+        match self.data.iter_mut().find(|entry| entry.os_type == VERSION) {
+            Some(existing) => *existing = entry,
+            None => self.data.insert(0, entry),
+        }
+
+        self
+    }
+
+    /// ## Getting the version
+    /// Reads the version of the `icnV` entry.
+    /// Returns `None` if the family has no `icnV` entry.
+    pub fn version(&self) -> Result<Option<f32>, IcnsError> {
+        let entry = match self.data.iter().find(|entry| entry.os_type == VERSION) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        match *entry.data {
+            [a, b, c, d] => Ok(Some(f32::from_be_bytes([a, b, c, d]))),
+            _ => Err(IcnsError::LengthMismatch {
+                expected: 4,
+                actual: entry.data.len(),
+            }),
+        }
+    }
+
+    /// ## Setting the name
+    /// Stores the name as UTF-8 in the `name` entry.
+    /// An existing `name` entry is replaced.
+    pub fn set_name(&mut self, name: &str) -> &mut Self {
+        self.replace_data(IcnsDataEntry::new(
+            NAME,
+            name.as_bytes().to_vec().into_boxed_slice(),
+        ))
+    }
+
+    /// ## Getting the name
+    /// Reads the name of the `name` entry.
+    /// Returns `None` if the family has no `name` entry.
+    pub fn name(&self) -> Option<String> {
+        self.data
+            .iter()
+            .find(|entry| entry.os_type == NAME)
+            .map(|entry| String::from_utf8_lossy(&entry.data).to_string())
+    }
+
+    /// ## Getting an entry
+    /// Returns the first entry with the OSType of the format.
+    pub fn get(&self, format: IconFormats) -> Option<&IcnsDataEntry> {
+        let os_type = format.get_bytes();
+
+        self.data.iter().find(|entry| entry.os_type == os_type)
+    }
+
+    /// ## Decoding an image
+    /// Decodes the entry of the given format as an image.
+    /// The legacy RGB types are combined with their matching 8-bit mask
+    /// and returned as an RGBA image. If the mask is missing, the image
+    /// is fully opaque.
+    pub fn image(&self, format: IconFormats) -> Result<DynamicImage, IcnsError> {
+        let entry = match self.get(format) {
+            Some(entry) => entry.clone(),
+            None => return Err(IcnsError::MissingEntry(format)),
+        };
+
+        let mut parser = ImageParser::new();
+        parser.format(format).data(entry);
+
+        if let Some(mask) = format.get_mask() {
+            parser.mask(self.get(mask).cloned());
+
+            return Ok(DynamicImage::ImageRgba8(parser.parse()?.to_rgba8()));
+        }
+
+        parser.parse()
+    }
+
+    /// ## Finding the best image
+    /// Returns the best square image that is at least as large as the
+    /// size: the smallest one, and of those the best format
+    /// (PNG, ARGB, RGB, ...). Images that can't be decoded
+    /// (e.g. JPEG 2000) are skipped.
+    pub(crate) fn best_image(&self, size: u32) -> Option<(IconFormats, DynamicImage)> {
+        let mut formats = self
+            .data
+            .iter()
+            .filter_map(|entry| IconFormats::from_bytes(entry.os_type))
+            .filter(|format| format.get_format() != FileFormat::MASK)
+            .filter(|format| {
+                let (width, height) = format.get_dimensions();
+                width == height && width as u32 >= size
+            })
+            .collect::<Vec<_>>();
+        formats.sort_by_key(|format| (format.get_size(), format.get_format().rank()));
+
+        formats
+            .into_iter()
+            .find_map(|format| Some((format, self.image(format).ok()?)))
+    }
+
+    /// ## Decoding all images
+    /// Decodes every known image entry in the family.
+    /// Masks are not returned on their own; they are combined with
+    /// their RGB image instead (see `.image()`).
+    pub fn images(&self) -> Result<Vec<(IconFormats, DynamicImage)>, IcnsError> {
+        let mut images = Vec::new();
+
+        for entry in &self.data {
+            let format = match IconFormats::from_bytes(entry.os_type) {
+                Some(format) => format,
+                None => continue,
+            };
+
+            if format.get_format() == FileFormat::MASK {
+                continue;
+            }
+
+            images.push((format, self.image(format)?));
+        }
+
+        Ok(images)
+    }
+
+    /// ## Getting the records
+    /// Returns the OSType and length of every entry in the order they
+    /// are written to the file. This is the data followed by the
+    /// nested dark mode family.
+    fn records(&self) -> Vec<([u8; 4], u32)> {
+        let mut records = self
+            .data
+            .iter()
+            .map(|entry| (entry.os_type, entry.len()))
+            .collect::<Vec<_>>();
+
+        if let Some(dark) = &self.dark {
+            records.push((DARK, 8 + dark.len()));
+        }
+
+        records
+    }
+
     /// ## Creating the table of contents
     /// The table of contents is the first entry in the file.
-    /// It contains the OSType of each entry and the length of each entry.
+    /// It contains the OSType of each entry and the length of each entry
+    /// (including the 8 byte header).
     pub fn create_contents_table(&self) -> IcnsDataEntry {
-        let mut buffer = Vec::with_capacity(8 * self.data.len()); // Each entry is 8 bytes
+        let records = self.records();
+        let mut buffer = Vec::with_capacity(8 * records.len()); // Each entry is 8 bytes
 
-        for data in &self.data {
-            buffer.extend_from_slice(&data.os_type);
-            buffer.extend_from_slice(&((&data).data.len() as u32).to_be_bytes());
+        for (os_type, length) in records {
+            buffer.extend_from_slice(&os_type);
+            buffer.extend_from_slice(&length.to_be_bytes());
         }
 
-        IcnsDataEntry::new(
-            [0x54, 0x4F, 0x43, 0x20], // "TOC "
-            buffer.into_boxed_slice(),
-        )
+        IcnsDataEntry::new(TOC, buffer.into_boxed_slice())
+    }
+
+    /// ## Length
+    /// The length of the compiled file, including the header
+    /// and the table of contents.
+    pub fn len(&self) -> u32 {
+        let records = self.records();
+
+        // Header + TOC header + TOC + entries
+        16 + 8 * records.len() as u32 + records.iter().map(|(_, length)| length).sum::<u32>()
+    }
+
+    /// ## Is empty
+    /// Returns true if the family has no entries and no dark mode family.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty() && self.dark.is_none()
+    }
+
+    /// ## Parsing an ICNS file
+    /// Parses a complete ICNS file back into an `IconFamily`.
+    /// The magic bytes and the total length are validated and every
+    /// OSType / length record is read into an `IcnsDataEntry`.
+    /// The table of contents is skipped because it is recreated by
+    /// `.build()` and the nested dark mode family is parsed as well.
+    ///
+    /// ```rust
+    /// use icns_rs::encode::{IcnsDataEntry, IconFamily};
+    ///
+    /// let mut family = IconFamily::new();
+    /// family.add_data(IcnsDataEntry::new(*b"ic07", vec![0x01, 0x02].into_boxed_slice()));
+    ///
+    /// let parsed = IconFamily::parse(&family.build()).unwrap();
+    ///
+    /// assert_eq!(parsed, family);
+    /// ```
+    pub fn parse(data: &[u8]) -> Result<Self, IcnsError> {
+        Self::parse_family(data, false)
+    }
+
+    /// Parses a family, `dark` is set for the nested dark mode family
+    /// which can't contain another one
+    fn parse_family(data: &[u8], dark: bool) -> Result<Self, IcnsError> {
+        if data.len() < 8 {
+            return Err(IcnsError::TruncatedData { offset: data.len() });
+        }
+
+        if data[0..4] != MAGIC {
+            return Err(IcnsError::BadMagic([data[0], data[1], data[2], data[3]]));
+        }
+
+        let total_size = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        if total_size != data.len() {
+            return Err(IcnsError::LengthMismatch {
+                expected: total_size,
+                actual: data.len(),
+            });
+        }
+
+        let mut family = Self::new();
+
+        // Walk every OSType / length record
+        let mut i = 8;
+        while i < data.len() {
+            if i + 8 > data.len() {
+                return Err(IcnsError::TruncatedData { offset: i });
+            }
+
+            let os_type = [data[i], data[i + 1], data[i + 2], data[i + 3]];
+            let length =
+                u32::from_be_bytes([data[i + 4], data[i + 5], data[i + 6], data[i + 7]]) as usize;
+
+            // The length includes the 8 byte header
+            if length < 8 || i + length > data.len() {
+                return Err(IcnsError::TruncatedData { offset: i });
+            }
+
+            if os_type == DARK {
+                if dark {
+                    return Err(IcnsError::NestedDarkFamily { offset: i });
+                }
+
+                family.set_dark(Some(Self::parse_family(&data[i + 8..i + length], true)?));
+            } else if os_type != TOC {
+                family.add_data(IcnsDataEntry::new(
+                    os_type,
+                    data[i + 8..i + length].to_vec().into_boxed_slice(),
+                ));
+            }
+
+            i += length;
+        }
+
+        Ok(family)
+    }
+
+    /// ## Reading an ICNS file
+    /// Reads a complete ICNS file from a reader and parses it.
+    /// See `.parse()` for more information.
+    pub fn read(mut reader: impl Read) -> Result<Self, IcnsError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        Self::parse(&data)
     }
 
     /// ## Building the ICNS file
     /// Building the file will create the table of contents
     /// and compile all the data into a single file.
     pub fn build(&self) -> Box<[u8]> {
-        // Calculate the total size of the file
-        let contents_table = self.create_contents_table();
+        let mut buffer = Vec::with_capacity(self.len() as usize);
 
-        // Insert the TOC first
-        let mut data = Vec::with_capacity(self.data.len() + 1);
-        data.push(contents_table);
-        for d in &self.data {
-            data.push(d.clone());
-        }
+        // Writing to a Vec can't fail
+        self.write_to(&mut buffer).unwrap();
 
-        let total_size = data.iter().map(|data| data.len()).sum::<u32>();
-        let mut buffer = Vec::with_capacity(8 + total_size as usize);
+        buffer.into_boxed_slice()
+    }
 
-        // Add the magic bytes, the total size and the data
-        buffer.extend_from_slice(&MAGIC);
-        buffer.extend_from_slice(&(8 + total_size as u32).to_be_bytes());
-        for data in &data {
-            buffer.extend_from_slice(&data.build());
+    /// ## Writing the ICNS file
+    /// Writes the file to a writer. The header and the table of
+    /// contents are computed up front and the entries are streamed
+    /// directly, so large families can be written to files or sockets
+    /// without building the whole file in memory.
+    ///
+    /// ```no_run
+    /// use icns_rs::encode::IconFamily;
+    /// use std::{fs::File, io::BufWriter};
+    ///
+    /// let family = IconFamily::read(File::open("in.icns").unwrap()).unwrap();
+    ///
+    /// let mut file = BufWriter::new(File::create("out.icns").unwrap());
+    /// family.write_to(&mut file).unwrap();
+    /// ```
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        // Add the magic bytes, the total size and the TOC
+        w.write_all(&MAGIC)?;
+        w.write_all(&self.len().to_be_bytes())?;
+        self.create_contents_table().write_to(w)?;
+
+        for entry in &self.data {
+            entry.write_to(w)?;
         }
 
-        buffer.into_boxed_slice()
+        if let Some(dark) = &self.dark {
+            w.write_all(&DARK)?;
+            w.write_all(&(8 + dark.len()).to_be_bytes())?;
+            dark.write_to(w)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -126,4 +503,209 @@ mod tests {
 
         assert_eq!(entry.build(), result.into_boxed_slice());
     }
+
+    #[test]
+    fn parse_icon_family() {
+        let mut family = super::IconFamily::new();
+        family.add_data(super::IcnsDataEntry::new(
+            [0x69, 0x63, 0x30, 0x37], // ic07
+            vec![0x00, 0x01, 0x02, 0x03].into_boxed_slice(),
+        ));
+        family.add_data(super::IcnsDataEntry::new(
+            [0x69, 0x63, 0x30, 0x38], // ic08
+            vec![].into_boxed_slice(),
+        ));
+
+        let built = family.build();
+
+        assert_eq!(super::IconFamily::parse(&built).unwrap(), family);
+        assert_eq!(super::IconFamily::read(&built[..]).unwrap(), family);
+    }
+
+    #[test]
+    fn contents_table_lengths() {
+        let data = vec![0x00, 0x01, 0x02, 0x03, 0x04];
+
+        let mut family = super::IconFamily::new();
+        family.add_data(super::IcnsDataEntry::new(
+            *b"ic07",
+            data.clone().into_boxed_slice(),
+        ));
+
+        // Each record holds the entry length including its 8 byte header
+        let table = family.create_contents_table();
+        assert_eq!(&table.data[0..4], b"ic07");
+        assert_eq!(
+            u32::from_be_bytes(table.data[4..8].try_into().unwrap()) as usize,
+            8 + data.len()
+        );
+    }
+
+    #[test]
+    fn dark_mode_family() {
+        let mut dark = super::IconFamily::new();
+        dark.add_data(super::IcnsDataEntry::new(
+            *b"ic07",
+            vec![0x04, 0x05, 0x06].into_boxed_slice(),
+        ));
+
+        let mut family = super::IconFamily::new();
+        family.add_data(super::IcnsDataEntry::new(
+            *b"ic07",
+            vec![0x01, 0x02, 0x03].into_boxed_slice(),
+        ));
+        family.set_dark(Some(dark.clone()));
+
+        let built = family.build();
+
+        // The TOC lists both entries with their full length
+        assert_eq!(
+            &built[8..32],
+            &[
+                0x54, 0x4F, 0x43, 0x20, 0x00, 0x00, 0x00, 0x18, // TOC (24 bytes)
+                0x69, 0x63, 0x30, 0x37, 0x00, 0x00, 0x00, 0x0B, // ic07 (11 bytes)
+                0xFD, 0xD9, 0x2F, 0xA8, 0x00, 0x00, 0x00, 0x2B, // Dark (43 bytes)
+            ]
+        );
+        assert_eq!(built.len(), 8 + 24 + 11 + 43);
+        assert_eq!(family.len() as usize, built.len());
+
+        // Streaming writes the same bytes
+        let mut streamed = Vec::new();
+        family.write_to(&mut streamed).unwrap();
+        assert_eq!(&*streamed, &*built);
+
+        let parsed = super::IconFamily::parse(&built).unwrap();
+
+        assert_eq!(parsed.dark.as_deref(), Some(&dark));
+        assert_eq!(parsed, family);
+    }
+
+    #[test]
+    fn info_entry() {
+        use crate::plist::{Dictionary, Value};
+
+        let mut family = super::IconFamily::new();
+        assert_eq!(family.info().unwrap(), None);
+
+        let mut info = Dictionary::new();
+        info.insert("name".to_string(), Value::String("First".to_string()));
+        family.set_info(&info);
+
+        info.insert("name".to_string(), Value::String("Second".to_string()));
+        info.insert("source".to_string(), Value::String("ci".to_string()));
+        family.set_info(&info);
+
+        // The entry is replaced
+        assert_eq!(family.data.len(), 1);
+        assert_eq!(&family.data[0].os_type, b"info");
+
+        let parsed = super::IconFamily::parse(&family.build()).unwrap();
+        assert_eq!(parsed.info().unwrap(), Some(info));
+    }
+
+    #[test]
+    fn version_and_name_entries() {
+        let mut family = super::IconFamily::new();
+        family.add_data(super::IcnsDataEntry::new(
+            *b"ic07",
+            vec![0x01].into_boxed_slice(),
+        ));
+
+        assert_eq!(family.version().unwrap(), None);
+        assert_eq!(family.name(), None);
+
+        family
+            .set_version(1.0)
+            .set_version(12.0)
+            .set_name("AppIcon");
+
+        // The version comes first and is replaced
+        assert_eq!(&family.data[0].os_type, b"icnV");
+        assert_eq!(&*family.data[0].data, &[0x41, 0x40, 0x00, 0x00]);
+        assert_eq!(family.data.len(), 3);
+
+        let parsed = super::IconFamily::parse(&family.build()).unwrap();
+        assert_eq!(parsed.version().unwrap(), Some(12.0));
+        assert_eq!(parsed.name(), Some("AppIcon".to_string()));
+    }
+
+    #[test]
+    fn parse_example_file() {
+        let data = include_bytes!("../example.icns");
+        let family = super::IconFamily::parse(data).unwrap();
+
+        assert!(!family.data.is_empty());
+        assert_eq!(family.build().len(), data.len());
+    }
+
+    #[test]
+    fn decode_images_with_masks() {
+        let mut family = super::IconFamily::new();
+        let planes = |r: u8, g: u8, b: u8, pixels: usize| {
+            let mut raw = vec![r; pixels];
+            raw.extend(vec![g; pixels]);
+            raw.extend(vec![b; pixels]);
+
+            crate::packbits::compress(raw.into_boxed_slice())
+        };
+
+        family.add_data(super::IcnsDataEntry::new(*b"is32", planes(1, 2, 3, 256)));
+        family.add_data(super::IcnsDataEntry::new(*b"il32", planes(1, 1, 1, 1024)));
+        family.add_data(super::IcnsDataEntry::new(
+            *b"s8mk",
+            vec![0x80; 256].into_boxed_slice(),
+        ));
+
+        // With a mask
+        let image = family.image(super::IconFormats::IS32).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [0x01, 0x02, 0x03, 0x80]);
+
+        // Without a mask
+        let image = family.image(super::IconFormats::IL32).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [0x01, 0x01, 0x01, 0xFF]);
+
+        // Masks are merged into their image
+        let images = family.images().unwrap();
+        assert_eq!(images.len(), 2);
+
+        assert!(family.image(super::IconFormats::IC07).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_files() {
+        // Bad magic
+        assert!(super::IconFamily::parse(&[0x00; 8]).is_err());
+        // Length mismatch
+        assert!(
+            super::IconFamily::parse(&[0x69, 0x63, 0x6e, 0x73, 0x00, 0x00, 0x00, 0x09]).is_err()
+        );
+        // Truncated entry
+        assert!(super::IconFamily::parse(&[
+            0x69, 0x63, 0x6e, 0x73, 0x00, 0x00, 0x00, 0x10, // Header
+            0x69, 0x63, 0x30, 0x37, 0x00, 0x00, 0x00, 0x10, // ic07 (16 bytes)
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn parse_nested_dark_family() {
+        // A dark mode family inside a dark mode family
+        let mut data = vec![0x69, 0x63, 0x6e, 0x73, 0x00, 0x00, 0x00, 0x08];
+        for _ in 0..2 {
+            let mut outer = vec![0x69, 0x63, 0x6e, 0x73];
+            outer.extend_from_slice(&(data.len() as u32 + 16).to_be_bytes());
+            outer.extend_from_slice(&super::DARK);
+            outer.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+            outer.extend_from_slice(&data);
+
+            data = outer;
+        }
+
+        assert!(matches!(
+            super::IconFamily::parse(&data),
+            Err(crate::IcnsError::NestedDarkFamily { offset: 8 })
+        ));
+        assert!(super::IconFamily::parse(&data[16..]).is_ok());
+    }
 }
@@ -0,0 +1,123 @@
+use std::{error::Error, fmt, io};
+
+use crate::{formats::IconFormats, packbits::PackBitsError};
+
+use image::{
+    error::{DecodingError, ImageFormatHint},
+    ImageError,
+};
+
+/// # ICNS Errors
+/// The errors that can occur while encoding or decoding an ICNS file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IcnsError {
+    /// The image could not be encoded or decoded (e.g. a PNG failure)
+    Image(ImageError),
+    /// The data is stored in a format that is not supported
+    /// (e.g. JPEG 2000 instead of PNG). Contains the first 4 bytes.
+    UnsupportedFormat([u8; 4]),
+    /// The image does not have the dimensions of its icon type
+    InvalidDimensions {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// The data ends before the structure at the offset is complete
+    TruncatedData { offset: usize },
+    /// The data does not start with the expected magic bytes
+    BadMagic([u8; 4]),
+    /// The length of the data does not match the declared length
+    LengthMismatch { expected: usize, actual: usize },
+    /// The nested dark mode family at the offset contains another
+    /// dark mode family. Apple only nests one level.
+    NestedDarkFamily { offset: usize },
+    /// The PackBits compressed data is malformed
+    PackBits(PackBitsError),
+    /// The property list of the `info` entry is malformed
+    InvalidPlist(String),
+    /// The `Contents.json` file of an asset catalog is malformed
+    InvalidJson(String),
+    /// The icon family does not contain an entry of the icon type
+    MissingEntry(IconFormats),
+    /// The icon family does not contain any image that can be decoded
+    NoImages,
+    /// Reading or writing failed
+    Io(io::Error),
+}
+
+impl fmt::Display for IcnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IcnsError::Image(e) => write!(f, "Image error: {}", e),
+            IcnsError::UnsupportedFormat(bytes) => {
+                write!(f, "Unsupported data format (starts with {:02X?})", bytes)
+            }
+            IcnsError::InvalidDimensions { expected, actual } => write!(
+                f,
+                "Invalid dimensions (expected {}x{}, got {}x{})",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            IcnsError::TruncatedData { offset } => {
+                write!(f, "Truncated data at offset {}", offset)
+            }
+            IcnsError::BadMagic(bytes) => write!(f, "Bad magic bytes {:02X?}", bytes),
+            IcnsError::LengthMismatch { expected, actual } => write!(
+                f,
+                "Length mismatch (expected {} bytes, got {})",
+                expected, actual
+            ),
+            IcnsError::NestedDarkFamily { offset } => {
+                write!(f, "Nested dark mode family at offset {}", offset)
+            }
+            IcnsError::PackBits(e) => write!(f, "PackBits error: {}", e),
+            IcnsError::InvalidPlist(message) => write!(f, "Invalid plist: {}", message),
+            IcnsError::InvalidJson(message) => write!(f, "Invalid JSON: {}", message),
+            IcnsError::MissingEntry(format) => {
+                write!(f, "Icon family has no {:?} entry", format)
+            }
+            IcnsError::NoImages => write!(f, "Icon family has no images"),
+            IcnsError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl Error for IcnsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IcnsError::Image(e) => Some(e),
+            IcnsError::PackBits(e) => Some(e),
+            IcnsError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageError> for IcnsError {
+    fn from(e: ImageError) -> Self {
+        IcnsError::Image(e)
+    }
+}
+
+impl From<PackBitsError> for IcnsError {
+    fn from(e: PackBitsError) -> Self {
+        IcnsError::PackBits(e)
+    }
+}
+
+impl From<io::Error> for IcnsError {
+    fn from(e: io::Error) -> Self {
+        IcnsError::Io(e)
+    }
+}
+
+/// Allows using the `image` crate integration (`codec`) with `?`
+/// in functions that return an `ImageResult`
+impl From<IcnsError> for ImageError {
+    fn from(e: IcnsError) -> Self {
+        match e {
+            IcnsError::Image(e) => e,
+            IcnsError::Io(e) => ImageError::IoError(e),
+            e => ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("ICNS".into()), e)),
+        }
+    }
+}
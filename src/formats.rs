@@ -5,6 +5,24 @@ pub enum FileFormat {
     ARGB,
     MASK,
     PNG,
+    MONO,
+    PALETTE4,
+    PALETTE8,
+}
+
+impl FileFormat {
+    /// Ranks the formats by quality (lower is better)
+    pub(crate) fn rank(self) -> u8 {
+        match self {
+            FileFormat::PNG => 0,
+            FileFormat::ARGB => 1,
+            FileFormat::RGB => 2,
+            FileFormat::PALETTE8 => 3,
+            FileFormat::PALETTE4 => 4,
+            FileFormat::MONO => 5,
+            FileFormat::MASK => 6,
+        }
+    }
 }
 
 /// # ICNS Types
@@ -119,6 +137,66 @@ pub enum IconFormats {
     /// - Format: PNG
     /// - OS: Mac OS X 10.7+
     ICP6,
+    /// - OSName: ICN#
+    /// - Size: 32x32
+    /// - Format: 1-bit mono icon with 1-bit mask
+    /// - OS: System 1.0+
+    ICN,
+    /// - OSName: ics#
+    /// - Size: 16x16
+    /// - Format: 1-bit mono icon with 1-bit mask
+    /// - OS: System 6.0+
+    ICS,
+    /// - OSName: icm#
+    /// - Size: 16x12
+    /// - Format: 1-bit mono icon with 1-bit mask
+    /// - OS: System 6.0+
+    ICM,
+    /// - OSName: ich#
+    /// - Size: 48x48
+    /// - Format: 1-bit mono icon with 1-bit mask
+    /// - OS: System 8.5+
+    ICH,
+    /// - OSName: icl4
+    /// - Size: 32x32
+    /// - Format: 4-bit palette icon
+    /// - OS: System 1.0+
+    ICL4,
+    /// - OSName: icl8
+    /// - Size: 32x32
+    /// - Format: 8-bit palette icon
+    /// - OS: System 7.0+
+    ICL8,
+    /// - OSName: ics4
+    /// - Size: 16x16
+    /// - Format: 4-bit palette icon
+    /// - OS: System 7.0+
+    ICS4,
+    /// - OSName: ics8
+    /// - Size: 16x16
+    /// - Format: 8-bit palette icon
+    /// - OS: System 7.0+
+    ICS8,
+    /// - OSName: icm4
+    /// - Size: 16x12
+    /// - Format: 4-bit palette icon
+    /// - OS: System 7.0+
+    ICM4,
+    /// - OSName: icm8
+    /// - Size: 16x12
+    /// - Format: 8-bit palette icon
+    /// - OS: System 7.0+
+    ICM8,
+    /// - OSName: ich4
+    /// - Size: 48x48
+    /// - Format: 4-bit palette icon
+    /// - OS: System 8.5+
+    ICH4,
+    /// - OSName: ich8
+    /// - Size: 48x48
+    /// - Format: 8-bit palette icon
+    /// - OS: System 8.5+
+    ICH8,
 }
 
 impl IconFormats {
@@ -146,6 +224,53 @@ impl IconFormats {
         ]
     }
 
+    /// Get the icon type from its OSType bytes.
+    /// Returns `None` if the OSType is not a known icon type.
+    pub fn from_bytes(bytes: [u8; 4]) -> Option<IconFormats> {
+        IconFormats::all()
+            .into_iter()
+            .find(|format| format.get_bytes() == bytes)
+    }
+
+    /// Get every supported icon type.
+    pub fn all() -> Vec<IconFormats> {
+        vec![
+            IconFormats::IS32,
+            IconFormats::IL32,
+            IconFormats::IH32,
+            IconFormats::IT32,
+            IconFormats::S8MK,
+            IconFormats::L8MK,
+            IconFormats::H8MK,
+            IconFormats::T8MK,
+            IconFormats::IC04,
+            IconFormats::IC05,
+            IconFormats::IC07,
+            IconFormats::IC08,
+            IconFormats::IC09,
+            IconFormats::IC10,
+            IconFormats::IC11,
+            IconFormats::IC12,
+            IconFormats::IC13,
+            IconFormats::IC14,
+            IconFormats::ICP4,
+            IconFormats::ICP5,
+            IconFormats::ICP6,
+            IconFormats::ICN,
+            IconFormats::ICS,
+            IconFormats::ICM,
+            IconFormats::ICH,
+            IconFormats::ICL4,
+            IconFormats::ICL8,
+            IconFormats::ICS4,
+            IconFormats::ICS8,
+            IconFormats::ICM4,
+            IconFormats::ICM8,
+            IconFormats::ICH4,
+            IconFormats::ICH8,
+        ]
+    }
+
     pub fn get_format(&self) -> FileFormat {
         match self {
             IconFormats::IS32 => FileFormat::RGB,
@@ -169,6 +294,40 @@ impl IconFormats {
             IconFormats::ICP4 => FileFormat::PNG,
             IconFormats::ICP5 => FileFormat::PNG,
             IconFormats::ICP6 => FileFormat::PNG,
+            IconFormats::ICN => FileFormat::MONO,
+            IconFormats::ICS => FileFormat::MONO,
+            IconFormats::ICM => FileFormat::MONO,
+            IconFormats::ICH => FileFormat::MONO,
+            IconFormats::ICL4 => FileFormat::PALETTE4,
+            IconFormats::ICL8 => FileFormat::PALETTE8,
+            IconFormats::ICS4 => FileFormat::PALETTE4,
+            IconFormats::ICS8 => FileFormat::PALETTE8,
+            IconFormats::ICM4 => FileFormat::PALETTE4,
+            IconFormats::ICM8 => FileFormat::PALETTE8,
+            IconFormats::ICH4 => FileFormat::PALETTE4,
+            IconFormats::ICH8 => FileFormat::PALETTE8,
+        }
+    }
+
+    /// Get the mask that holds the transparency of the icon type.
+    /// Only the legacy RGB and palette types store their alpha in a
+    /// separate mask. The palette types use the 1-bit mask of the
+    /// matching mono icon.
+    pub fn get_mask(&self) -> Option<IconFormats> {
+        match self {
+            IconFormats::IS32 => Some(IconFormats::S8MK),
+            IconFormats::IL32 => Some(IconFormats::L8MK),
+            IconFormats::IH32 => Some(IconFormats::H8MK),
+            IconFormats::IT32 => Some(IconFormats::T8MK),
+            IconFormats::ICL4 => Some(IconFormats::ICN),
+            IconFormats::ICL8 => Some(IconFormats::ICN),
+            IconFormats::ICS4 => Some(IconFormats::ICS),
+            IconFormats::ICS8 => Some(IconFormats::ICS),
+            IconFormats::ICM4 => Some(IconFormats::ICM),
+            IconFormats::ICM8 => Some(IconFormats::ICM),
+            IconFormats::ICH4 => Some(IconFormats::ICH),
+            IconFormats::ICH8 => Some(IconFormats::ICH),
+            _ => None,
         }
     }
 
@@ -195,6 +354,29 @@ impl IconFormats {
             IconFormats::ICP4 => 16,
             IconFormats::ICP5 => 32,
             IconFormats::ICP6 => 64,
+            IconFormats::ICN => 32,
+            IconFormats::ICS => 16,
+            IconFormats::ICM => 16,
+            IconFormats::ICH => 48,
+            IconFormats::ICL4 => 32,
+            IconFormats::ICL8 => 32,
+            IconFormats::ICS4 => 16,
+            IconFormats::ICS8 => 16,
+            IconFormats::ICM4 => 16,
+            IconFormats::ICM8 => 16,
+            IconFormats::ICH4 => 48,
+            IconFormats::ICH8 => 48,
+        }
+    }
+
+    /// Get the width and height of the icon type.
+    /// All icon types are square except for the mini icons (16x12).
+    pub fn get_dimensions(&self) -> (usize, usize) {
+        match self {
+            IconFormats::ICM => (16, 12),
+            IconFormats::ICM4 => (16, 12),
+            IconFormats::ICM8 => (16, 12),
+            _ => (self.get_size(), self.get_size()),
         }
     }
 
@@ -221,6 +403,18 @@ impl IconFormats {
             IconFormats::ICP4 => [0x69, 0x63, 0x70, 0x34], //icp4
             IconFormats::ICP5 => [0x69, 0x63, 0x70, 0x35], //icp5
             IconFormats::ICP6 => [0x69, 0x63, 0x70, 0x36], //icp6
+            IconFormats::ICN => [0x49, 0x43, 0x4e, 0x23],  //ICN#
+            IconFormats::ICS => [0x69, 0x63, 0x73, 0x23],  //ics#
+            IconFormats::ICM => [0x69, 0x63, 0x6d, 0x23],  //icm#
+            IconFormats::ICH => [0x69, 0x63, 0x68, 0x23],  //ich#
+            IconFormats::ICL4 => [0x69, 0x63, 0x6c, 0x34], //icl4
+            IconFormats::ICL8 => [0x69, 0x63, 0x6c, 0x38], //icl8
+            IconFormats::ICS4 => [0x69, 0x63, 0x73, 0x34], //ics4
+            IconFormats::ICS8 => [0x69, 0x63, 0x73, 0x38], //ics8
+            IconFormats::ICM4 => [0x69, 0x63, 0x6d, 0x34], //icm4
+            IconFormats::ICM8 => [0x69, 0x63, 0x6d, 0x38], //icm8
+            IconFormats::ICH4 => [0x69, 0x63, 0x68, 0x34], //ich4
+            IconFormats::ICH8 => [0x69, 0x63, 0x68, 0x38], //ich8
         }
     }
 }
@@ -0,0 +1,214 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    builder::{encode_png, ImageBuilder},
+    encode::IconFamily,
+    error::IcnsError,
+    parser::PNG_MAGIC,
+};
+
+use image::imageops::FilterType;
+
+/// # Hicolor sizes
+/// The application icon sizes of the freedesktop hicolor theme
+/// written by default.
+pub const HICOLOR_SIZES: [u32; 9] = [16, 22, 24, 32, 48, 64, 128, 256, 512];
+
+/// ## HicolorExporter
+/// Exports an `IconFamily` as a freedesktop hicolor icon theme tree:
+/// `<root>/<N>x<N>/apps/<name>.png`. The root is usually
+/// `share/icons/hicolor` inside the package.
+///
+/// Every size uses the best image with the exact size; PNG entries
+/// are copied as they are. Otherwise the closest larger image (e.g.
+/// RGB with its mask) is decoded, resized and encoded as PNG.
+/// Sizes without an image that is at least as large are skipped.
+///
+/// The theme's `index.theme` belongs to the hicolor theme and is not
+/// written, see `HicolorExporter::index_theme_fragment` for the entries
+/// of sizes it doesn't list.
+///
+/// ```no_run
+/// use icns_rs::{encode::IconFamily, hicolor::HicolorExporter};
+///
+/// let family = IconFamily::parse(&std::fs::read("app.icns").unwrap()).unwrap();
+///
+/// HicolorExporter::new("my-app")
+///     .export(&family, "pkg/usr/share/icons/hicolor")
+///     .unwrap();
+/// ```
+pub struct HicolorExporter {
+    name: String,
+    sizes: Vec<u32>,
+    filter: FilterType,
+}
+
+impl HicolorExporter {
+    /// Creates a new exporter for the icon name (without `.png`)
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            sizes: HICOLOR_SIZES.to_vec(),
+            filter: FilterType::Nearest,
+        }
+    }
+
+    /// Sets the sizes to be exported
+    pub fn sizes(&mut self, sizes: Vec<u32>) -> &mut Self {
+        self.sizes = sizes;
+
+        self
+    }
+
+    /// Sets the filter type to be used when resizing the images
+    /// See `ImageBuilder::filter` for more information
+    pub fn filter(&mut self, filter: FilterType) -> &mut Self {
+        self.filter = filter;
+
+        self
+    }
+
+    /// Exports the family and returns the paths of the written files
+    /// The name must be a file name, not a path.
+    pub fn export(
+        &self,
+        family: &IconFamily,
+        root: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>, IcnsError> {
+        let root = root.as_ref();
+        let mut files = Vec::new();
+
+        // The files must be inside the root
+        if Path::new(&self.name).file_name() != Some(self.name.as_ref()) || self.name.contains('\\')
+        {
+            return Err(IcnsError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid icon name \"{}\"", self.name),
+            )));
+        }
+
+        for &size in &self.sizes {
+            let (format, image) = match family.best_image(size) {
+                Some(best) => best,
+                None => continue,
+            };
+
+            let data = match family.get(format) {
+                Some(entry)
+                    if format.get_size() as u32 == size && entry.data.starts_with(&PNG_MAGIC) =>
+                {
+                    entry.data.to_vec()
+                }
+                _ => {
+                    let mut image_encoder = ImageBuilder::new();
+                    image_encoder.data(image).filter(self.filter);
+
+                    encode_png(&image_encoder.resized_to_dimensions(size, size))?
+                }
+            };
+
+            let directory = root.join(format!("{0}x{0}", size)).join("apps");
+            fs::create_dir_all(&directory)?;
+
+            let path = directory.join(format!("{}.png", self.name));
+            fs::write(&path, data)?;
+
+            files.push(path);
+        }
+
+        Ok(files)
+    }
+
+    /// ## Index theme fragment
+    /// Returns the `[<N>x<N>/apps]` sections of the sizes, to be merged
+    /// into an `index.theme` that doesn't list them yet. The directories
+    /// also need to be added to its `Directories` key.
+    ///
+    /// ```rust
+    /// use icns_rs::hicolor::HicolorExporter;
+    ///
+    /// assert_eq!(
+    ///     HicolorExporter::index_theme_fragment(&[16]),
+    ///     "[16x16/apps]\nSize=16\nContext=Applications\nType=Threshold\n"
+    /// );
+    /// ```
+    pub fn index_theme_fragment(sizes: &[u32]) -> String {
+        sizes
+            .iter()
+            .map(|size| {
+                format!(
+                    "[{0}x{0}/apps]\nSize={0}\nContext=Applications\nType=Threshold\n",
+                    size
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{formats::IconFormats, IcnsEncoder};
+
+    use image::{DynamicImage, GenericImageView};
+
+    #[test]
+    fn export_hicolor() {
+        let path = std::env::temp_dir().join(format!("icns-rs-hicolor-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+
+        let mut encoder = IcnsEncoder::new();
+        encoder.data(DynamicImage::new_rgba8(64, 64)).formats(vec![
+            IconFormats::IS32,
+            IconFormats::S8MK,
+            IconFormats::IC12,
+        ]);
+        let family = IconFamily::parse(&encoder.build().unwrap()).unwrap();
+
+        let files = HicolorExporter::new("app")
+            .sizes(vec![16, 24, 64, 128])
+            .export(&family, &path)
+            .unwrap();
+
+        // 128 is skipped because there is no larger image
+        assert_eq!(
+            files,
+            vec![
+                path.join("16x16/apps/app.png"),
+                path.join("24x24/apps/app.png"),
+                path.join("64x64/apps/app.png"),
+            ]
+        );
+
+        // is32 + s8mk is re-encoded, ic12 is copied as it is
+        let image = image::open(path.join("16x16/apps/app.png")).unwrap();
+        assert_eq!(image.dimensions(), (16, 16));
+        assert_eq!(image.to_rgba8().get_pixel(0, 0).0, [0x00; 4]);
+        assert_eq!(
+            fs::read(path.join("64x64/apps/app.png")).unwrap(),
+            family.get(IconFormats::IC12).unwrap().data.to_vec()
+        );
+
+        // The theme's index.theme is left alone
+        assert!(!path.join("index.theme").exists());
+        assert_eq!(
+            HicolorExporter::index_theme_fragment(&[16, 24]),
+            "[16x16/apps]\nSize=16\nContext=Applications\nType=Threshold\n\n\
+             [24x24/apps]\nSize=24\nContext=Applications\nType=Threshold\n"
+        );
+
+        // Names can't leave the root
+        for name in ["../../x", "a/b", "..", ""] {
+            assert!(matches!(
+                HicolorExporter::new(name).export(&family, &path),
+                Err(IcnsError::Io(e)) if e.kind() == io::ErrorKind::InvalidInput
+            ));
+        }
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    builder::{encode_png, ImageBuilder},
+    encode::IconFamily,
+    error::IcnsError,
+    parser::PNG_MAGIC,
+    IcnsEncoder,
+};
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+/// # ICO sizes
+/// The sizes written by `IconFamily::to_ico`
+/// (the sizes Windows uses for application icons).
+pub const ICO_SIZES: [u32; 4] = [16, 32, 48, 256];
+
+/// The length of the ICONDIR header and of every ICONDIRENTRY
+const DIR_LEN: usize = 6;
+const DIR_ENTRY_LEN: usize = 16;
+/// The length of the BITMAPINFOHEADER
+const BITMAP_HEADER_LEN: usize = 40;
+
+/// ## IcoEncoder
+/// Encodes images as a Windows `.ico` file.
+/// Large sizes are stored as PNG, small sizes as 32-bit BMP (DIB)
+/// with a 1-bit AND mask for older versions of Windows.
+/// Like the `IcnsEncoder`, every size is resized from the source
+/// with its exact size, or the closest larger source.
+///
+/// ```no_run
+/// use icns_rs::ico::IcoEncoder;
+///
+/// let mut encoder = IcoEncoder::new();
+/// encoder.data(image::open("icon.png").unwrap());
+///
+/// std::fs::write("icon.ico", encoder.build().unwrap()).unwrap();
+/// ```
+pub struct IcoEncoder {
+    data: BTreeMap<u32, DynamicImage>,
+    sizes: Vec<u32>,
+    filter: FilterType,
+    png_size: u32,
+}
+
+impl Default for IcoEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IcoEncoder {
+    /// Creates a new IcoEncoder for the sizes in `ICO_SIZES`
+    pub fn new() -> Self {
+        Self {
+            data: BTreeMap::new(),
+            sizes: ICO_SIZES.to_vec(),
+            filter: FilterType::Nearest,
+            png_size: 256,
+        }
+    }
+
+    /// Sets the image data. This replaces every source added with `add_data`.
+    pub fn data(&mut self, data: DynamicImage) -> &mut Self {
+        self.data.clear();
+
+        self.add_data(data)
+    }
+
+    /// Adds a source image keyed by its width
+    /// See `IcnsEncoder::add_data` for how the sources are picked.
+    pub fn add_data(&mut self, data: DynamicImage) -> &mut Self {
+        self.data.insert(data.width(), data);
+
+        self
+    }
+
+    /// Sets the sizes to be encoded (1 to 256 pixels)
+    pub fn sizes(&mut self, sizes: Vec<u32>) -> &mut Self {
+        self.sizes = sizes;
+
+        self
+    }
+
+    /// Sets the filter type to be used when resizing the image
+    /// See `ImageBuilder::filter` for more information
+    pub fn filter(&mut self, filter: FilterType) -> &mut Self {
+        self.filter = filter;
+
+        self
+    }
+
+    /// Sets the smallest size that is stored as PNG
+    /// Smaller sizes are stored as BMP. The default is 256, because
+    /// Windows XP and older can't read PNG images in icons.
+    pub fn png_size(&mut self, png_size: u32) -> &mut Self {
+        self.png_size = png_size;
+
+        self
+    }
+
+    /// Encodes the images as an ICO file
+    pub fn build(&self) -> Result<Box<[u8]>, IcnsError> {
+        // One builder per source, so every source is only copied once
+        let mut image_encoders = BTreeMap::new();
+        let mut images = Vec::with_capacity(self.sizes.len());
+
+        for &size in &self.sizes {
+            if size == 0 || size > 256 {
+                return Err(IcnsError::InvalidDimensions {
+                    expected: (256, 256),
+                    actual: (size, size),
+                });
+            }
+
+            let width = IcnsEncoder::source_width(&self.data, size);
+            let image_encoder = image_encoders.entry(width).or_insert_with(|| {
+                let mut image_encoder = ImageBuilder::new();
+                if let Some(source) = self.data.get(&width) {
+                    image_encoder.data(source.clone());
+                }
+                image_encoder.filter(self.filter);
+
+                image_encoder
+            });
+
+            let resized = image_encoder.resized_to_dimensions(size, size);
+            let data = if size >= self.png_size {
+                encode_png(&resized)?
+            } else {
+                bmp_image(&resized)
+            };
+
+            images.push((resized.dimensions(), data));
+        }
+
+        // Header, directory and images
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&0u16.to_le_bytes()); // Reserved
+        buffer.extend_from_slice(&1u16.to_le_bytes()); // Type: icon
+        buffer.extend_from_slice(&(images.len() as u16).to_le_bytes());
+
+        let mut offset = DIR_LEN + DIR_ENTRY_LEN * images.len();
+        for ((width, height), data) in &images {
+            // 256 is stored as 0
+            buffer.push(*width as u8);
+            buffer.push(*height as u8);
+            buffer.push(0x00); // No palette
+            buffer.push(0x00); // Reserved
+            buffer.extend_from_slice(&1u16.to_le_bytes()); // Planes
+            buffer.extend_from_slice(&32u16.to_le_bytes()); // Bits per pixel
+            buffer.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buffer.extend_from_slice(&(offset as u32).to_le_bytes());
+
+            offset += data.len();
+        }
+
+        for (_, data) in images {
+            buffer.extend_from_slice(&data);
+        }
+
+        Ok(buffer.into_boxed_slice())
+    }
+}
+
+/// Encodes an image as a 32-bit BMP (without the file header)
+/// followed by the AND mask. The rows are stored bottom-up.
+fn bmp_image(image: &DynamicImage) -> Vec<u8> {
+    let rgba8 = image.to_rgba8();
+    let (width, height) = rgba8.dimensions();
+    let mask_row = mask_row_len(width);
+    let pixels_len = (width * height * 4) as usize;
+    let mask_len = mask_row * height as usize;
+
+    let mut buffer = Vec::with_capacity(BITMAP_HEADER_LEN + pixels_len + mask_len);
+    buffer.extend_from_slice(&(BITMAP_HEADER_LEN as u32).to_le_bytes());
+    buffer.extend_from_slice(&(width as i32).to_le_bytes());
+    // The height includes the AND mask
+    buffer.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+    buffer.extend_from_slice(&1u16.to_le_bytes()); // Planes
+    buffer.extend_from_slice(&32u16.to_le_bytes()); // Bits per pixel
+    buffer.extend_from_slice(&0u32.to_le_bytes()); // No compression
+    buffer.extend_from_slice(&((pixels_len + mask_len) as u32).to_le_bytes());
+    buffer.extend_from_slice(&[0x00; 16]); // Resolution and palette
+
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let [r, g, b, a] = rgba8.get_pixel(x, y).0;
+            buffer.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+
+    // Set bits are transparent, the opposite of the 1-bit icns masks where
+    // set bits are opaque. Pixels that are at least half opaque are visible
+    for y in (0..height).rev() {
+        let mut row = vec![0x00; mask_row];
+        for x in 0..width {
+            if rgba8.get_pixel(x, y)[3] < 0x80 {
+                row[x as usize / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        buffer.extend_from_slice(&row);
+    }
+
+    buffer
+}
+
+/// The length of a row of the AND mask (padded to 4 bytes)
+fn mask_row_len(width: u32) -> usize {
+    (width as usize).div_ceil(32) * 4
+}
+
+/// ## Reading an ICO file
+/// Decodes every image of a Windows `.ico` file as RGBA.
+/// PNG images and BMP images with 1, 4, 8, 24 and 32 bits per pixel
+/// are supported. The images can be passed to `IcnsEncoder::add_data`
+/// to convert the ICO file to an ICNS file.
+///
+/// ```no_run
+/// use icns_rs::{ico::read_ico, IcnsEncoder, IconFormats};
+///
+/// let mut encoder = IcnsEncoder::new();
+/// for image in read_ico(&std::fs::read("icon.ico").unwrap()).unwrap() {
+///     encoder.add_data(image);
+/// }
+/// encoder.formats(IconFormats::recommended());
+///
+/// std::fs::write("icon.icns", encoder.build().unwrap()).unwrap();
+/// ```
+pub fn read_ico(data: &[u8]) -> Result<Vec<DynamicImage>, IcnsError> {
+    if data.len() < DIR_LEN {
+        return Err(IcnsError::TruncatedData { offset: data.len() });
+    }
+
+    // Reserved (0) and type (1 = icon)
+    if data[0..4] != [0x00, 0x00, 0x01, 0x00] {
+        return Err(IcnsError::BadMagic([data[0], data[1], data[2], data[3]]));
+    }
+
+    let count = u16::from_le_bytes([data[4], data[5]]) as usize;
+    let mut images = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let entry = DIR_LEN + DIR_ENTRY_LEN * i;
+        let header = data
+            .get(entry..entry + DIR_ENTRY_LEN)
+            .ok_or(IcnsError::TruncatedData { offset: entry })?;
+
+        let length = u32::from_le_bytes([header[8], header[9], header[10], header[11]]) as usize;
+        let offset = u32::from_le_bytes([header[12], header[13], header[14], header[15]]) as usize;
+        let image = data
+            .get(offset..offset.saturating_add(length))
+            .ok_or(IcnsError::TruncatedData { offset: entry })?;
+
+        if image.starts_with(&PNG_MAGIC) {
+            images.push(image::load_from_memory_with_format(
+                image,
+                image::ImageFormat::Png,
+            )?);
+        } else {
+            images.push(DynamicImage::ImageRgba8(read_bmp(image, offset)?));
+        }
+    }
+
+    Ok(images)
+}
+
+/// Decodes a BMP image of an ICO file with its AND mask
+/// The offset of the image is only used for errors
+fn read_bmp(data: &[u8], offset: usize) -> Result<RgbaImage, IcnsError> {
+    let truncated = IcnsError::TruncatedData { offset };
+    if data.len() < BITMAP_HEADER_LEN {
+        return Err(truncated);
+    }
+
+    let header_len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let width = i32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    let height = i32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+    let bit_count = u16::from_le_bytes([data[14], data[15]]);
+    let compression = u32::from_le_bytes([data[16], data[17], data[18], data[19]]);
+    let colors_used = u32::from_le_bytes([data[32], data[33], data[34], data[35]]) as usize;
+
+    if compression != 0 || ![1, 4, 8, 24, 32].contains(&bit_count) {
+        return Err(IcnsError::UnsupportedFormat([
+            data[14], data[15], data[16], data[17],
+        ]));
+    }
+    if width <= 0 || height == 0 || width > 256 || height.unsigned_abs() > 512 {
+        return Err(truncated);
+    }
+
+    // The height includes the AND mask, negative heights are top-down
+    let (width, height, top_down) = (width as u32, height.unsigned_abs() / 2, height < 0);
+
+    let palette_len = if bit_count <= 8 {
+        if colors_used == 0 {
+            1 << bit_count
+        } else {
+            colors_used
+        }
+    } else {
+        0
+    };
+    let palette = data
+        .get(header_len..header_len + palette_len * 4)
+        .ok_or(IcnsError::TruncatedData { offset })?;
+
+    let row_len = (width as usize * bit_count as usize).div_ceil(32) * 4;
+    let pixels_start = header_len + palette.len();
+    let mask_start = pixels_start + row_len * height as usize;
+    let mask_row = mask_row_len(width);
+
+    let pixels = data
+        .get(pixels_start..mask_start)
+        .ok_or(IcnsError::TruncatedData { offset })?;
+    // Some files leave out the AND mask of 32-bit images
+    let mask = data.get(mask_start..mask_start + mask_row * height as usize);
+
+    let mut image = RgbaImage::new(width, height);
+
+    for y in 0..height {
+        let row_index = if top_down { y } else { height - 1 - y } as usize;
+        let row = &pixels[row_len * row_index..row_len * (row_index + 1)];
+
+        for x in 0..width as usize {
+            let [b, g, r, a] = match bit_count {
+                32 => [row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]],
+                24 => [row[x * 3], row[x * 3 + 1], row[x * 3 + 2], 0xFF],
+                _ => {
+                    let bits = bit_count as usize;
+                    let byte = row[x * bits / 8];
+                    let shift = 8 - bits - (x * bits % 8);
+                    let index = (byte >> shift) as usize & ((1 << bits) - 1);
+                    let color = palette.get(index * 4..index * 4 + 3).unwrap_or(&[0x00; 3]);
+
+                    [color[0], color[1], color[2], 0xFF]
+                }
+            };
+
+            image.put_pixel(x as u32, y, Rgba([r, g, b, a]));
+        }
+    }
+
+    // The AND mask is used unless the image has its own alpha channel
+    let has_alpha = bit_count == 32 && image.pixels().any(|pixel| pixel[3] != 0x00);
+    if let (Some(mask), false) = (mask, has_alpha) {
+        for y in 0..height {
+            let row_index = if top_down { y } else { height - 1 - y } as usize;
+            let row = &mask[mask_row * row_index..mask_row * (row_index + 1)];
+
+            for x in 0..width {
+                let transparent = row[x as usize / 8] & (0x80 >> (x % 8)) != 0;
+                image.get_pixel_mut(x, y)[3] = if transparent { 0x00 } else { 0xFF };
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+impl IconFamily {
+    /// ## Converting to ICO
+    /// Encodes the family as a Windows `.ico` file with the sizes in
+    /// `ICO_SIZES`. Every size uses the best image with the exact size,
+    /// or the closest larger image (see `IcoEncoder`). Sizes without
+    /// an image that is at least as large are skipped.
+    pub fn to_ico(&self) -> Result<Box<[u8]>, IcnsError> {
+        let mut encoder = IcoEncoder::new();
+        let mut sizes = Vec::new();
+
+        for size in ICO_SIZES {
+            if let Some((_, image)) = self.best_image(size) {
+                encoder.add_data(image);
+                sizes.push(size);
+            }
+        }
+
+        if sizes.is_empty() {
+            return Err(IcnsError::NoImages);
+        }
+
+        encoder.sizes(sizes).build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::IconFormats;
+
+    fn source() -> DynamicImage {
+        let mut image = RgbaImage::from_pixel(64, 64, Rgba([0x10, 0x20, 0x30, 0xFF]));
+        // The top half is transparent
+        for (_, _, pixel) in image.enumerate_pixels_mut().take(64 * 32) {
+            *pixel = Rgba([0x00, 0x00, 0x00, 0x00]);
+        }
+
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn ico_round_trip() {
+        let mut encoder = IcoEncoder::new();
+        encoder.data(source()).sizes(vec![16, 32, 64]).png_size(64);
+        let data = encoder.build().unwrap();
+
+        assert_eq!(&data[0..6], &[0x00, 0x00, 0x01, 0x00, 0x03, 0x00]);
+
+        let images = read_ico(&data).unwrap();
+        let sizes = images.iter().map(|image| image.width()).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![16, 32, 64]);
+
+        // The BMP and the PNG images keep the transparency
+        for image in images {
+            let image = image.to_rgba8();
+            assert_eq!(image.get_pixel(0, 0).0, [0x00, 0x00, 0x00, 0x00]);
+            assert_eq!(
+                image.get_pixel(0, image.height() - 1).0,
+                [0x10, 0x20, 0x30, 0xFF]
+            );
+        }
+
+        // Other decoders can read the BMP images
+        let data = IcoEncoder::new()
+            .data(source())
+            .sizes(vec![32])
+            .build()
+            .unwrap();
+        let image = image::load_from_memory_with_format(&data, image::ImageFormat::Ico)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(image.get_pixel(0, 31).0, [0x10, 0x20, 0x30, 0xFF]);
+
+        assert!(IcoEncoder::new().sizes(vec![512]).build().is_err());
+    }
+
+    #[test]
+    fn read_legacy_bmp() {
+        // A 2x2 1-bit image: white and black with the top right pixel transparent
+        let mut bmp = vec![0x00; BITMAP_HEADER_LEN];
+        bmp[0] = BITMAP_HEADER_LEN as u8;
+        bmp[4] = 2; // Width
+        bmp[8] = 4; // Height (with the mask)
+        bmp[12] = 1; // Planes
+        bmp[14] = 1; // Bits per pixel
+        bmp.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0x00]); // Palette
+        bmp.extend_from_slice(&[0b1000_0000, 0, 0, 0, 0b0100_0000, 0, 0, 0]); // Pixels
+        bmp.extend_from_slice(&[0b0000_0000, 0, 0, 0, 0b0100_0000, 0, 0, 0]); // Mask
+
+        let image = read_bmp(&bmp, 0).unwrap();
+
+        // The rows are stored bottom-up
+        assert_eq!(image.get_pixel(0, 0).0, [0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(image.get_pixel(1, 0).0[3], 0x00);
+        assert_eq!(image.get_pixel(0, 1).0, [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(image.get_pixel(1, 1).0, [0x00, 0x00, 0x00, 0xFF]);
+
+        // The smallest height can't be negated
+        bmp[8..12].copy_from_slice(&i32::MIN.to_le_bytes());
+        assert!(read_bmp(&bmp, 0).is_err());
+    }
+
+    #[test]
+    fn icon_family_to_ico() {
+        let mut encoder = IcnsEncoder::new();
+        encoder.data(source()).formats(vec![
+            IconFormats::IS32,
+            IconFormats::S8MK,
+            IconFormats::IL32,
+            IconFormats::L8MK,
+            IconFormats::IC12,
+        ]);
+        let family = IconFamily::parse(&encoder.build().unwrap()).unwrap();
+
+        // 16 and 32 are used as they are, 48 is downscaled from ic12
+        // and 256 is skipped
+        let images = read_ico(&family.to_ico().unwrap()).unwrap();
+        let sizes = images.iter().map(|image| image.width()).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![16, 32, 48]);
+
+        assert!(matches!(
+            IconFamily::new().to_ico(),
+            Err(IcnsError::NoImages)
+        ));
+    }
+}
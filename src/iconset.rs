@@ -0,0 +1,249 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    builder::ImageBuilder,
+    encode::{IcnsDataEntry, IconFamily},
+    error::IcnsError,
+    formats::{FileFormat, IconFormats},
+    parser::PNG_MAGIC,
+};
+
+use image::GenericImageView;
+
+/// # Iconset files
+/// The files of an Apple `.iconset` directory and the icon type
+/// they are stored as. This is the same mapping `iconutil` uses.
+pub const ICONSET_FILES: [(&str, IconFormats); 10] = [
+    ("icon_16x16.png", IconFormats::ICP4),
+    ("icon_16x16@2x.png", IconFormats::IC11),
+    ("icon_32x32.png", IconFormats::ICP5),
+    ("icon_32x32@2x.png", IconFormats::IC12),
+    ("icon_128x128.png", IconFormats::IC07),
+    ("icon_128x128@2x.png", IconFormats::IC13),
+    ("icon_256x256.png", IconFormats::IC08),
+    ("icon_256x256@2x.png", IconFormats::IC14),
+    ("icon_512x512.png", IconFormats::IC09),
+    ("icon_512x512@2x.png", IconFormats::IC10),
+];
+
+/// ## Reading an iconset
+/// Reads the PNG files of an `.iconset` directory into an `IconFamily`.
+/// Missing files are skipped and other files are ignored, but the
+/// directory itself must exist.
+/// Files that are not PNGs (but can be opened by the `image` crate)
+/// are re-encoded as PNG.
+///
+/// ```no_run
+/// use icns_rs::iconset::read_iconset;
+///
+/// let family = read_iconset("AppIcon.iconset").unwrap();
+///
+/// std::fs::write("AppIcon.icns", family.build()).unwrap();
+/// ```
+pub fn read_iconset(path: impl AsRef<Path>) -> Result<IconFamily, IcnsError> {
+    let path = path.as_ref();
+    let mut family = IconFamily::new();
+
+    if !path.is_dir() {
+        return Err(IcnsError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", path.display()),
+        )));
+    }
+
+    for (name, format) in ICONSET_FILES {
+        let file = path.join(name);
+        if !file.is_file() {
+            continue;
+        }
+
+        family.add_data(read_image(&file, format)?);
+    }
+
+    Ok(family)
+}
+
+/// Reads an image file as the entry of a PNG format
+/// The image must have the dimensions of the format. Files that
+/// are not PNGs are re-encoded as PNG.
+pub(crate) fn read_image(file: &Path, format: IconFormats) -> Result<IcnsDataEntry, IcnsError> {
+    let data = fs::read(file)?;
+    let image = image::load_from_memory(&data)?;
+
+    let (width, height) = format.get_dimensions();
+    if image.dimensions() != (width as u32, height as u32) {
+        return Err(IcnsError::InvalidDimensions {
+            expected: (width as u32, height as u32),
+            actual: image.dimensions(),
+        });
+    }
+
+    if data.starts_with(&PNG_MAGIC) {
+        return Ok(IcnsDataEntry::new(
+            format.get_bytes(),
+            data.into_boxed_slice(),
+        ));
+    }
+
+    ImageBuilder::new().data(image).format(format).build()
+}
+
+/// ## Writing an iconset
+/// Writes an `IconFamily` as the PNG files of an `.iconset` directory.
+/// PNG entries are written as they are. If the matching PNG entry is
+/// missing, another image of the same size (e.g. ARGB or RGB with its
+/// mask) is decoded and encoded as PNG instead. Sizes without any
+/// image that can be decoded (e.g. only JPEG 2000) are skipped.
+pub fn write_iconset(family: &IconFamily, path: impl AsRef<Path>) -> Result<(), IcnsError> {
+    let path = path.as_ref();
+    fs::create_dir_all(path)?;
+
+    for (name, format) in ICONSET_FILES {
+        if let Some(entry) = family.get(format) {
+            if entry.data.starts_with(&PNG_MAGIC) {
+                fs::write(path.join(name), &entry.data)?;
+                continue;
+            }
+        }
+
+        // Look for another image of the same size, the best formats first
+        let mut candidates = IconFormats::all()
+            .into_iter()
+            .filter(|other| other.get_dimensions() == format.get_dimensions())
+            .filter(|other| other.get_format() != FileFormat::MASK)
+            .filter(|other| family.get(*other).is_some())
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|other| (*other != format, other.get_format().rank()));
+
+        // Skip the images that can't be decoded (e.g. JPEG 2000)
+        let mut image = None;
+        for other in candidates {
+            match family.image(other) {
+                Ok(decoded) => {
+                    image = Some(decoded);
+                    break;
+                }
+                Err(IcnsError::UnsupportedFormat(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let image = match image {
+            Some(image) => image,
+            None => continue,
+        };
+
+        let entry = ImageBuilder::new().data(image).format(format).build()?;
+        fs::write(path.join(name), &entry.data)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IcnsEncoder;
+
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("icns-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+
+        path
+    }
+
+    #[test]
+    fn iconset_round_trip() {
+        let path = temp_dir("iconset").join("AppIcon.iconset");
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            64,
+            64,
+            Rgba([0x10, 0x20, 0x30, 0x80]),
+        ));
+
+        let mut encoder = IcnsEncoder::new();
+        encoder.data(source).formats(vec![
+            IconFormats::IS32,
+            IconFormats::S8MK,
+            IconFormats::IC11,
+            IconFormats::IC12,
+        ]);
+        let family = IconFamily::parse(&encoder.build().unwrap()).unwrap();
+
+        write_iconset(&family, &path).unwrap();
+
+        // is32 + s8mk is used for the missing icp4 and ic11 for icp5
+        let names = [
+            "icon_16x16.png",
+            "icon_16x16@2x.png",
+            "icon_32x32.png",
+            "icon_32x32@2x.png",
+        ];
+        for name in names {
+            assert!(path.join(name).is_file());
+        }
+        assert!(!path.join("icon_128x128.png").exists());
+
+        // The PNG entries are copied as they are
+        assert_eq!(
+            fs::read(path.join("icon_16x16@2x.png")).unwrap(),
+            family.get(IconFormats::IC11).unwrap().data.to_vec()
+        );
+
+        let parsed = read_iconset(&path).unwrap();
+        assert_eq!(parsed.data.len(), 4);
+
+        let image = parsed.image(IconFormats::ICP4).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [0x10, 0x20, 0x30, 0x80]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn iconset_skips_jpeg_2000() {
+        let path = temp_dir("iconset-jpeg-2000");
+        let png = IcnsEncoder::new()
+            .data(DynamicImage::new_rgba8(256, 256))
+            .formats(vec![IconFormats::IC13])
+            .build()
+            .unwrap();
+
+        // ic08 is tried first, but JPEG 2000 can't be decoded
+        let mut family = IconFamily::parse(&png).unwrap();
+        family.add_data(IcnsDataEntry::new(
+            *b"ic08",
+            vec![0x00, 0x00, 0x00, 0x0C, 0x6A, 0x50, 0x20, 0x20].into_boxed_slice(),
+        ));
+
+        write_iconset(&family, &path).unwrap();
+
+        assert!(path.join("icon_256x256.png").is_file());
+        assert!(path.join("icon_128x128@2x.png").is_file());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn iconset_invalid_dimensions() {
+        let path = temp_dir("iconset-invalid");
+        fs::create_dir_all(&path).unwrap();
+
+        DynamicImage::new_rgba8(20, 20)
+            .save(path.join("icon_16x16.png"))
+            .unwrap();
+
+        assert!(matches!(
+            read_iconset(&path),
+            Err(IcnsError::InvalidDimensions { .. })
+        ));
+
+        fs::remove_dir_all(&path).unwrap();
+
+        // The directory must exist
+        assert!(matches!(
+            read_iconset(&path),
+            Err(IcnsError::Io(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+    }
+}
@@ -0,0 +1,370 @@
+use crate::error::IcnsError;
+
+/// # JSON values
+/// A minimal JSON implementation used for the `Contents.json`
+/// files of asset catalogs. Objects keep the order of their keys.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the value of a key of an object
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Returns the string, if the value is a string
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the values, if the value is an array
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Writes the value in the format Xcode uses
+    pub(crate) fn to_json(&self) -> String {
+        let mut buffer = String::new();
+        self.write_json(&mut buffer, 0);
+        buffer.push('\n');
+
+        buffer
+    }
+
+    fn write_json(&self, buffer: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth + 1);
+
+        match self {
+            Value::Null => buffer.push_str("null"),
+            Value::Boolean(value) => buffer.push_str(&value.to_string()),
+            Value::Number(value) => buffer.push_str(&value.to_string()),
+            Value::String(value) => buffer.push_str(&escape(value)),
+            Value::Array(values) => {
+                buffer.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    buffer.push_str(if i == 0 { "\n" } else { ",\n" });
+                    buffer.push_str(&indent);
+                    value.write_json(buffer, depth + 1);
+                }
+                if !values.is_empty() {
+                    buffer.push('\n');
+                    buffer.push_str(&"  ".repeat(depth));
+                }
+                buffer.push(']');
+            }
+            Value::Object(entries) => {
+                buffer.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    buffer.push_str(if i == 0 { "\n" } else { ",\n" });
+                    buffer.push_str(&indent);
+                    buffer.push_str(&escape(key));
+                    buffer.push_str(" : ");
+                    value.write_json(buffer, depth + 1);
+                }
+                if !entries.is_empty() {
+                    buffer.push('\n');
+                    buffer.push_str(&"  ".repeat(depth));
+                }
+                buffer.push('}');
+            }
+        }
+    }
+
+    /// Parses a JSON document
+    pub(crate) fn parse(text: &str) -> Result<Value, IcnsError> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.position != parser.text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+
+        Ok(value)
+    }
+}
+
+/// Quotes and escapes a string
+fn escape(value: &str) -> String {
+    let mut buffer = String::with_capacity(value.len() + 2);
+    buffer.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => buffer.push_str("\\\""),
+            '\\' => buffer.push_str("\\\\"),
+            '\n' => buffer.push_str("\\n"),
+            '\r' => buffer.push_str("\\r"),
+            '\t' => buffer.push_str("\\t"),
+            c if (c as u32) < 0x20 => buffer.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buffer.push(c),
+        }
+    }
+
+    buffer.push('"');
+    buffer
+}
+
+/// The nesting limit of arrays and objects
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> IcnsError {
+        IcnsError::InvalidJson(format!("{} at offset {}", message, self.position))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.text.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    /// Consumes the literal if the text continues with it
+    fn consume(&mut self, literal: &str) -> bool {
+        if self.text[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            return true;
+        }
+
+        false
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, IcnsError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+
+        self.skip_whitespace();
+
+        match self.text.get(self.position) {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ if self.consume("true") => Ok(Value::Boolean(true)),
+            _ if self.consume("false") => Ok(Value::Boolean(false)),
+            _ if self.consume("null") => Ok(Value::Null),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, IcnsError> {
+        self.position += 1;
+        let mut entries = Vec::new();
+
+        self.skip_whitespace();
+        if self.consume("}") {
+            return Ok(Value::Object(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.text.get(self.position) != Some(&b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+
+            self.skip_whitespace();
+            if !self.consume(":") {
+                return Err(self.error("expected ':'"));
+            }
+            entries.push((key, self.value(depth + 1)?));
+
+            self.skip_whitespace();
+            if self.consume("}") {
+                return Ok(Value::Object(entries));
+            }
+            if !self.consume(",") {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, IcnsError> {
+        self.position += 1;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.consume("]") {
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value(depth + 1)?);
+
+            self.skip_whitespace();
+            if self.consume("]") {
+                return Ok(Value::Array(values));
+            }
+            if !self.consume(",") {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, IcnsError> {
+        let start = self.position;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+            self.text.get(self.position)
+        {
+            self.position += 1;
+        }
+
+        std::str::from_utf8(&self.text[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Value::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, IcnsError> {
+        self.position += 1;
+        let mut bytes = Vec::new();
+
+        loop {
+            let byte = match self.text.get(self.position) {
+                Some(byte) => *byte,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.position += 1;
+
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.text.get(self.position) {
+                        Some(escaped) => *escaped,
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    self.position += 1;
+
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    /// Reads the hex digits of a `\u` escape (and its low surrogate)
+    fn unicode_escape(&mut self) -> Result<char, IcnsError> {
+        let high = self.hex()?;
+
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.consume("\\u") {
+                return Err(self.error("invalid surrogate"));
+            }
+            let low = self.hex()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("invalid surrogate"));
+            }
+
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| self.error("invalid character"))
+    }
+
+    fn hex(&mut self) -> Result<u32, IcnsError> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.position += 4;
+
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let value = Value::Object(vec![
+            (
+                "images".to_string(),
+                Value::Array(vec![Value::Object(vec![(
+                    "filename".to_string(),
+                    Value::String("a \"b\"\n".to_string()),
+                )])]),
+            ),
+            ("empty".to_string(), Value::Array(vec![])),
+            ("version".to_string(), Value::Number(1.0)),
+            ("flag".to_string(), Value::Boolean(true)),
+            ("none".to_string(), Value::Null),
+        ]);
+
+        let json = value.to_json();
+        assert!(json.starts_with("{\n  \"images\" : [\n    {\n      \"filename\" : "));
+        assert_eq!(Value::parse(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let value = Value::parse(r#"["\u00e9\ud83d\ude00\/", -1.5e2]"#).unwrap();
+
+        assert_eq!(
+            value,
+            Value::Array(vec![
+                Value::String("\u{e9}\u{1F600}/".to_string()),
+                Value::Number(-150.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for json in ["", "{", "[1,]", "{\"a\" 1}", "\"\\x\"", "[1] 2", "tru"] {
+            assert!(
+                matches!(Value::parse(json), Err(IcnsError::InvalidJson(_))),
+                "{}",
+                json
+            );
+        }
+    }
+}
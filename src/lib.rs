@@ -1,18 +1,46 @@
+pub mod appiconset;
 pub mod builder;
+pub mod codec;
 pub mod encode;
+pub mod error;
 pub mod formats;
+pub mod hicolor;
+pub mod ico;
+pub mod iconset;
+mod json;
 pub mod packbits;
+pub mod palette;
+pub mod parser;
+pub mod plist;
+pub mod reader;
+pub mod validate;
+
+use std::{collections::BTreeMap, io::Write};
 
 use builder::ImageBuilder;
 use encode::IconFamily;
+pub use error::IcnsError;
 pub use formats::IconFormats;
-use image::DynamicImage;
+use image::{imageops::FilterType, DynamicImage};
+use packbits::Compression;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 /// The main encoder struct
 /// Create a new encoder with `IcnsEncoder::new()`
 pub struct IcnsEncoder {
-    data: DynamicImage,
+    data: BTreeMap<u32, DynamicImage>,
+    dark: BTreeMap<u32, DynamicImage>,
     formats: Vec<IconFormats>,
+    filter: FilterType,
+    compression: Compression,
+    version: Option<f32>,
+}
+
+impl Default for IcnsEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IcnsEncoder {
@@ -68,14 +96,63 @@ impl IcnsEncoder {
     /// ```
     pub fn new() -> Self {
         Self {
-            data: DynamicImage::new_rgb8(1, 1),
+            data: BTreeMap::new(),
+            dark: BTreeMap::new(),
             formats: Vec::new(),
+            filter: FilterType::Nearest,
+            compression: Compression::Fast,
+            version: None,
         }
     }
 
     /// Sets the image data. Encode a png and pass it as a DynamicImage.
+    /// This replaces every source added with `add_data`.
     pub fn data(&mut self, data: DynamicImage) -> &mut Self {
-        self.data = data;
+        self.data.clear();
+
+        self.add_data(data)
+    }
+
+    /// ## Multiple sources
+    /// Adds a source image keyed by its width, replacing a source
+    /// with the same width. Every format is encoded from the source
+    /// with its exact size, or the closest larger source is downscaled.
+    /// If every source is smaller, the largest one is used.
+    ///
+    /// ```no_run
+    /// use icns_rs::{IcnsEncoder, IconFormats};
+    ///
+    /// let mut encoder = IcnsEncoder::new();
+    /// encoder
+    ///     .add_data(image::open("icon_16x16.png").unwrap())
+    ///     .add_data(image::open("icon_32x32.png").unwrap())
+    ///     .add_data(image::open("icon_1024x1024.png").unwrap())
+    ///     .formats(IconFormats::recommended());
+    ///
+    /// let data = encoder.build().unwrap();
+    /// ```
+    pub fn add_data(&mut self, data: DynamicImage) -> &mut Self {
+        self.data.insert(data.width(), data);
+
+        self
+    }
+
+    /// Sets the image data for the dark appearance.
+    /// The dark images are encoded in the same formats and stored
+    /// in a nested icon family.
+    pub fn dark_data(&mut self, data: Option<DynamicImage>) -> &mut Self {
+        self.dark.clear();
+
+        match data {
+            Some(data) => self.add_dark_data(data),
+            None => self,
+        }
+    }
+
+    /// Adds a source image for the dark appearance.
+    /// See `IcnsEncoder::add_data` for how the sources are picked.
+    pub fn add_dark_data(&mut self, data: DynamicImage) -> &mut Self {
+        self.dark.insert(data.width(), data);
 
         self
     }
@@ -87,19 +164,228 @@ impl IcnsEncoder {
         self
     }
 
+    /// Sets the filter type to be used when resizing the image
+    /// See `ImageBuilder::filter` for more information
+    pub fn filter(&mut self, filter: FilterType) -> &mut Self {
+        self.filter = filter;
+
+        self
+    }
+
+    /// Sets the PackBits compression of the RGB and ARGB images
+    /// See `ImageBuilder::compression` for more information
+    pub fn compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = compression;
+
+        self
+    }
+
+    /// Sets the version written to the `icnV` entry.
+    /// Apple's iconutil always writes this entry; by default it is omitted.
+    pub fn version(&mut self, version: Option<f32>) -> &mut Self {
+        self.version = version;
+
+        self
+    }
+
     /// Encodes the image as an ICNS file
-    pub fn build(&self) -> Result<Box<[u8]>, String> {
+    pub fn build(&self) -> Result<Box<[u8]>, IcnsError> {
+        Ok(self.icon_family()?.build())
+    }
+
+    /// Encodes the image and writes the ICNS file to a writer
+    /// See `IconFamily::write_to` for more information
+    pub fn write_to(&self, w: &mut impl Write) -> Result<(), IcnsError> {
+        self.icon_family()?.write_to(w)?;
+
+        Ok(())
+    }
+
+    /// Encodes the image as an icon family with the dark family
+    /// and the version
+    fn icon_family(&self) -> Result<IconFamily, IcnsError> {
+        let mut file = self.family(&self.data, &self.formats)?;
+
+        if !self.dark.is_empty() {
+            file.set_dark(Some(self.family(&self.dark, &self.formats)?));
+        }
+
+        if let Some(version) = self.version {
+            file.set_version(version);
+        }
+
+        Ok(file)
+    }
+
+    /// Encodes the sources in every format as an icon family
+    /// With the `rayon` feature the formats are encoded in parallel,
+    /// the entries are still added in the order of the formats
+    fn family(
+        &self,
+        data: &BTreeMap<u32, DynamicImage>,
+        formats: &[IconFormats],
+    ) -> Result<IconFamily, IcnsError> {
         let mut file = IconFamily::new();
 
-        let mut image_encoder = ImageBuilder::new();
-        image_encoder.data(self.data.clone());
+        // One builder per source, so every source is only copied once
+        let mut image_encoders = BTreeMap::new();
 
-        for format in &self.formats {
-            let image = image_encoder.format(format.clone()).build()?;
+        for format in formats {
+            let width = Self::source_width(data, format.get_size() as u32);
+            image_encoders.entry(width).or_insert_with(|| {
+                let mut image_encoder = ImageBuilder::new();
+                if let Some(source) = data.get(&width) {
+                    image_encoder.data(source.clone());
+                }
+                image_encoder
+                    .filter(self.filter)
+                    .compression(self.compression);
 
-            file.add_data(image);
+                image_encoder
+            });
         }
 
-        Ok(file.build())
+        // Every size is resized once by the shared builders and
+        // every format is encoded from the resized image
+        let encode = |format: &IconFormats| {
+            let image_encoder =
+                &image_encoders[&Self::source_width(data, format.get_size() as u32)];
+
+            image_encoder.build_resized(*format, &image_encoder.resized_to(*format))
+        };
+
+        #[cfg(feature = "rayon")]
+        let entries = formats
+            .par_iter()
+            .map(encode)
+            .collect::<Result<Vec<_>, _>>()?;
+        #[cfg(not(feature = "rayon"))]
+        let entries = formats.iter().map(encode).collect::<Result<Vec<_>, _>>()?;
+
+        for entry in entries {
+            file.add_data(entry);
+        }
+
+        Ok(file)
+    }
+
+    /// Returns the width of the source used for an image of the width:
+    /// the exact size, else the closest larger one, else the largest
+    pub(crate) fn source_width(data: &BTreeMap<u32, DynamicImage>, width: u32) -> u32 {
+        data.range(width..)
+            .next()
+            .or_else(|| data.iter().next_back())
+            .map_or(0, |(width, _)| *width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_dark_mode() {
+        let mut encoder = IcnsEncoder::new();
+        encoder
+            .data(DynamicImage::new_rgba8(32, 32))
+            .dark_data(Some(DynamicImage::new_rgb8(32, 32)))
+            .formats(vec![IconFormats::IC11, IconFormats::IC05]);
+
+        let family = IconFamily::parse(&encoder.build().unwrap()).unwrap();
+        let dark = family.dark.unwrap();
+
+        assert_eq!(family.data.len(), 2);
+        assert_eq!(dark.data.len(), 2);
+        assert_eq!(
+            dark.image(IconFormats::IC05)
+                .unwrap()
+                .to_rgba8()
+                .get_pixel(0, 0)
+                .0,
+            [0x00, 0x00, 0x00, 0xFF]
+        );
+    }
+
+    #[test]
+    fn encode_version() {
+        let mut encoder = IcnsEncoder::new();
+        encoder.formats(vec![IconFormats::IS32]);
+
+        let family = IconFamily::parse(&encoder.build().unwrap()).unwrap();
+        assert_eq!(family.version().unwrap(), None);
+
+        encoder.version(Some(12.0));
+
+        let family = IconFamily::parse(&encoder.build().unwrap()).unwrap();
+        assert_eq!(family.version().unwrap(), Some(12.0));
+        assert_eq!(&family.data[0].os_type, b"icnV");
+    }
+
+    #[test]
+    fn encode_multiple_sources() {
+        let source = |width, color| {
+            DynamicImage::ImageRgb8(image::RgbImage::from_pixel(width, width, image::Rgb(color)))
+        };
+
+        let mut encoder = IcnsEncoder::new();
+        encoder
+            .add_data(source(16, [0xFF, 0x00, 0x00]))
+            .add_data(source(64, [0x00, 0x00, 0xFF]))
+            .formats(vec![
+                IconFormats::IS32,
+                IconFormats::IL32,
+                IconFormats::IC07,
+            ]);
+
+        let family = IconFamily::parse(&encoder.build().unwrap()).unwrap();
+        let color = |format| family.image(format).unwrap().to_rgb8().get_pixel(0, 0).0;
+
+        // Exact match, downscaled from the closest larger and upscaled from the largest
+        assert_eq!(color(IconFormats::IS32), [0xFF, 0x00, 0x00]);
+        assert_eq!(color(IconFormats::IL32), [0x00, 0x00, 0xFF]);
+        assert_eq!(color(IconFormats::IC07), [0x00, 0x00, 0xFF]);
+
+        // `data` replaces every source
+        encoder.data(source(16, [0x00, 0xFF, 0x00]));
+
+        let family = IconFamily::parse(&encoder.build().unwrap()).unwrap();
+        assert_eq!(
+            family
+                .image(IconFormats::IL32)
+                .unwrap()
+                .to_rgb8()
+                .get_pixel(0, 0)
+                .0,
+            [0x00, 0xFF, 0x00]
+        );
+    }
+
+    #[test]
+    fn encode_in_format_order() {
+        let formats = IconFormats::recommended()
+            .into_iter()
+            .filter(|format| format.get_size() <= 64)
+            .collect::<Vec<_>>();
+
+        let mut encoder = IcnsEncoder::new();
+        encoder
+            .data(DynamicImage::new_rgba8(64, 64))
+            .formats(formats.clone());
+
+        let data = encoder.build().unwrap();
+        let family = IconFamily::parse(&data).unwrap();
+
+        let os_types = family
+            .data
+            .iter()
+            .map(|entry| entry.os_type)
+            .collect::<Vec<_>>();
+        let expected = formats
+            .iter()
+            .map(|format| format.get_bytes())
+            .collect::<Vec<_>>();
+
+        assert_eq!(os_types, expected);
+        assert_eq!(encoder.build().unwrap(), data);
     }
 }
@@ -1,3 +1,5 @@
+use std::{error::Error, fmt};
+
 /// To denote that a byte is repeated, the first byte of a sequence
 /// must be greater or equal to 128. A byte is 255 so because of this
 /// 255 - 128 = 127 is the maximum amount of bytes that can be repeated.
@@ -25,94 +27,243 @@ const ENCODE_REPEAT: u8 = 128;
 ///     vec![0x02, 0x01, 0x02, 0x02, 0x80, 0x03, 0x81, 0x04, 0x82, 0x05]
 ///         .into_boxed_slice()
 /// );
+#[allow(clippy::boxed_local)]
 pub fn compress(raw: Box<[u8]>) -> Box<[u8]> {
-    let mut buffers: Vec<Box<[u8]>> = vec![];
+    let mut buffer = Vec::with_capacity(raw.len() + raw.len() / MAX_LITERAL + 1);
+    compress_into(&raw, &mut buffer);
+
+    buffer.into_boxed_slice()
+}
 
-    // I'd be happy to use a iterator here
-    // FIXME: This is a mess
+/// # Compressing into a buffer
+/// Same as `compress`, but takes a slice and appends the compressed
+/// data to the buffer, so the buffer can be reused and several channels
+/// can be compressed into one buffer.
+///
+/// ```rust
+/// let mut buffer = vec![0x00, 0x00, 0x00, 0x00];
+///
+/// icns_rs::packbits::compress_into(&[0x01, 0x01, 0x01], &mut buffer);
+///
+/// assert_eq!(buffer, vec![0x00, 0x00, 0x00, 0x00, 0x80, 0x01]);
+/// ```
+pub fn compress_into(raw: &[u8], buffer: &mut Vec<u8>) {
     let mut i = 0;
     while i < raw.len() {
-        let byte = &raw[i];
-        // Check if last 1 or 2 bytes
-        if i + 2 >= raw.len() {
-            let length = raw.len() - i;
-            let mut buffer = Vec::with_capacity(1);
-            buffer.push(length as u8 - 1);
-            buffers.push(buffer.into_boxed_slice());
-            buffers.push(raw[i..].to_vec().into_boxed_slice());
-            break;
-        }
+        i += compress_run(&raw[i..], buffer);
+    }
+}
 
-        // Should be repeated if the next 2 bytes are the same
-        let should_repeat = byte == &raw[i + 1] && byte == &raw[i + 2];
+/// The bytes a run of `compress_run` can look at. With this many bytes
+/// left, a run is the same as with the complete data.
+const LOOKAHEAD: usize = MAX_REPEAT + 2;
 
-        if should_repeat {
-            let mut repeat_to = i + 2;
+/// # Streaming compression
+/// Same as `compress`, but compresses the bytes of an iterator as they
+/// come and appends the compressed data to the buffer. Only a few
+/// bytes are buffered, so channels don't have to be collected first.
+///
+/// ```rust
+/// let pixels = [[0x01, 0x02, 0x03], [0x01, 0x02, 0x03], [0x01, 0x02, 0x03]];
+/// let mut buffer = Vec::new();
+///
+/// // The red channel
+/// icns_rs::packbits::compress_iter(pixels.iter().map(|pixel| pixel[0]), &mut buffer);
+///
+/// assert_eq!(buffer, vec![0x80, 0x01]);
+/// ```
+pub fn compress_iter(bytes: impl IntoIterator<Item = u8>, buffer: &mut Vec<u8>) {
+    let mut window = Vec::with_capacity(LOOKAHEAD * 2);
+
+    for byte in bytes {
+        window.push(byte);
 
-            while repeat_to + 1 < raw.len()
-                && byte == &raw[repeat_to + 1]
-                && repeat_to - i + 1 < MAX_REPEAT
-            {
-                repeat_to += 1;
+        if window.len() == LOOKAHEAD * 2 {
+            let mut i = 0;
+            while window.len() - i >= LOOKAHEAD {
+                i += compress_run(&window[i..], buffer);
             }
+            window.drain(..i);
+        }
+    }
+
+    compress_into(&window, buffer);
+}
+
+/// Compresses the run at the start of the data
+/// Returns the amount of bytes that were compressed
+fn compress_run(raw: &[u8], buffer: &mut Vec<u8>) -> usize {
+    let byte = raw[0];
+
+    // Check if last 1 or 2 bytes
+    if raw.len() <= 2 {
+        buffer.push(raw.len() as u8 - 1);
+        buffer.extend_from_slice(raw);
 
+        return raw.len();
+    }
+
+    // Should be repeated if the next 2 bytes are the same
+    if byte == raw[1] && byte == raw[2] {
+        let mut repeat_to = 2;
+
+        while repeat_to + 1 < raw.len() && byte == raw[repeat_to + 1] && repeat_to + 1 < MAX_REPEAT
+        {
             repeat_to += 1;
+        }
+
+        // + 1 because the first byte is also included
+        let length = repeat_to + 1;
+        buffer.push(length as u8 - 3 + ENCODE_REPEAT);
+        buffer.push(byte);
+
+        return length;
+    }
+
+    // Should not be repeated
+    // Minimum length is 2 (that's why we check if we're at the last 2 bytes)
+    let mut buffer_to = 2;
+    let mut repeats = 1;
+    let mut repeat_index = buffer_to;
+
+    while buffer_to + 1 < raw.len() && buffer_to + 1 < MAX_LITERAL {
+        if raw[buffer_to] == raw[repeat_index] {
+            repeats += 1;
+            // If we have 2 repeats, we can stop
+            // It would be better to check to compress
+            if repeats > 2 {
+                break;
+            }
+        } else {
+            repeats = 1;
+            repeat_index = buffer_to;
+        }
+
+        buffer_to += 1;
+    }
+    buffer_to += 1;
+    if repeats > 2 {
+        buffer_to -= 3;
+    }
+
+    buffer.push(buffer_to as u8 - 1);
+    buffer.extend_from_slice(&raw[..buffer_to]);
+
+    buffer_to
+}
+
+/// The maximum length of a literal run (the header byte is length - 1)
+const MAX_LITERAL: usize = 128;
+
+/// # PackBits compression modes
+/// - `Fast`: The greedy encoder of `compress`
+/// - `Optimal`: The encoder of `compress_optimal`, which always
+///   produces the smallest output but is slower
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    Fast,
+    Optimal,
+}
+
+/// # Compressing with a mode
+/// Compresses the data with the encoder of the compression mode.
+pub fn compress_with(raw: &[u8], compression: Compression) -> Box<[u8]> {
+    let mut buffer = Vec::new();
+    compress_with_into(raw, compression, &mut buffer);
+
+    buffer.into_boxed_slice()
+}
+
+/// Same as `compress_with`, but appends the compressed data to the buffer
+pub fn compress_with_into(raw: &[u8], compression: Compression, buffer: &mut Vec<u8>) {
+    match compression {
+        Compression::Fast => compress_into(raw, buffer),
+        Compression::Optimal => compress_optimal_into(raw, buffer),
+    }
+}
 
-            let length = repeat_to - i; // + 1 because the first byte is also included
+/// # Optimal ICNS PackBits(like) compression
+/// Produces the smallest possible compressed data. Every position is
+/// either the start of a literal run (1 to 128 bytes) or of a repeated
+/// run (3 to 130 bytes) and the cheapest split of the data into runs is
+/// found with dynamic programming, starting from the end of the data.
+/// This is slower than `compress`, which decides greedily.
+///
+/// ```rust
+/// use icns_rs::packbits::{compress, compress_optimal, decompress};
+///
+/// let data = [0x01, 0x02, 0x02, 0x02];
+///
+/// let compressed = compress_optimal(&data);
+///
+/// assert_eq!(&*compressed, &[0x00, 0x01, 0x80, 0x02]);
+/// assert!(compressed.len() < compress(data.into()).len());
+/// assert_eq!(&*decompress(compressed), &data);
+/// ```
+pub fn compress_optimal(raw: &[u8]) -> Box<[u8]> {
+    let mut buffer = Vec::new();
+    compress_optimal_into(raw, &mut buffer);
 
-            let mut buffer = Vec::with_capacity(2);
-            buffer.push(length as u8 - 3 + ENCODE_REPEAT);
-            buffer.push(byte.clone());
+    buffer.into_boxed_slice()
+}
 
-            buffers.push(buffer.into_boxed_slice());
+/// Same as `compress_optimal`, but appends the compressed data to the buffer
+pub fn compress_optimal_into(raw: &[u8], buffer: &mut Vec<u8>) {
+    let length = raw.len();
 
-            // Skip the repeated bytes
-            i = repeat_to;
+    // The length of the run of equal bytes starting at every position
+    let mut runs = vec![0; length];
+    for i in (0..length).rev() {
+        runs[i] = if i + 1 < length && raw[i] == raw[i + 1] {
+            (runs[i + 1] + 1).min(MAX_REPEAT)
         } else {
-            // Should not be repeated
-            let mut buffer_to = i + 2;
-            // ^^ Minimum length is 2 (that's why we check if we're at the last 2 bytes)
-            let mut repeats = 1;
-            let mut repeat_index = buffer_to;
-
-            while buffer_to + 1 < raw.len() && buffer_to - i + 1 < ENCODE_REPEAT as usize {
-                if &raw[buffer_to] == &raw[repeat_index] {
-                    repeats += 1;
-                    // If we have 2 repeats, we can stop
-                    // It would be better to check to compress
-                    if repeats > 2 {
-                        break;
-                    }
-                } else {
-                    repeats = 1;
-                    repeat_index = buffer_to;
-                }
-
-                buffer_to += 1;
+            1
+        };
+    }
+
+    // The smallest compressed size of the data from every position
+    // and the run that starts there (repeated, length)
+    let mut costs = vec![0; length + 1];
+    let mut choices = vec![(false, 0); length];
+
+    for i in (0..length).rev() {
+        let mut best = (usize::MAX, (false, 0));
+
+        for literal in 1..=MAX_LITERAL.min(length - i) {
+            let cost = 1 + literal + costs[i + literal];
+            if cost < best.0 {
+                best = (cost, (false, literal));
             }
-            buffer_to += 1;
-            if repeats > 2 {
-                buffer_to -= 3;
+        }
+
+        for repeat in 3..=runs[i] {
+            let cost = 2 + costs[i + repeat];
+            if cost < best.0 {
+                best = (cost, (true, repeat));
             }
+        }
+
+        costs[i] = best.0;
+        choices[i] = best.1;
+    }
 
-            let length = buffer_to - i;
-            let mut buffer = Vec::with_capacity(length + 1);
-            buffer.push(length as u8 - 1);
-            buffer.extend_from_slice(&raw[i..buffer_to]);
+    buffer.reserve(costs[0]);
 
-            buffers.push(buffer.into_boxed_slice());
+    let mut i = 0;
+    while i < length {
+        let (repeated, run) = choices[i];
 
-            i = buffer_to;
+        if repeated {
+            buffer.push(run as u8 - 3 + ENCODE_REPEAT);
+            buffer.push(raw[i]);
+        } else {
+            buffer.push(run as u8 - 1);
+            buffer.extend_from_slice(&raw[i..i + run]);
         }
-    }
 
-    // Compact the buffers into a single buffer
-    let mut buffer = Vec::with_capacity(buffers.iter().map(|b| b.len()).sum());
-    for b in buffers {
-        buffer.extend_from_slice(&b);
+        i += run;
     }
-
-    buffer.into_boxed_slice()
 }
 
 /// # ICNS PackBits(like) decompression
@@ -141,48 +292,218 @@ pub fn compress(raw: Box<[u8]>) -> Box<[u8]> {
 ///     .into_boxed_slice()
 /// );
 /// ```
+///
+/// Malformed data does not panic: decompression stops at the first run
+/// that is cut off. Use `try_decompress` to detect malformed data.
+#[allow(clippy::boxed_local)]
 pub fn decompress(data: Box<[u8]>) -> Box<[u8]> {
-    let mut buffers: Vec<Box<[u8]>> = vec![];
+    match try_decompress(&data, None) {
+        Ok(buffer) => buffer,
+        // Everything before the offset was decompressed successfully
+        Err(PackBitsError::Truncated { offset }) => {
+            try_decompress(&data[..offset], None).unwrap_or_default()
+        }
+        Err(PackBitsError::LimitExceeded { .. }) => unreachable!(),
+    }
+}
 
-    // FIXME: Don't use a loop
-    let mut i = 0;
-    while i < data.len() {
-        // We know it's compressed if the first byte is greater or equal to 128
-        if data[i] >= ENCODE_REPEAT {
-            // How many times the byte is repeated
-            let repeats = data[i] - ENCODE_REPEAT + 3;
-            // ^^ + 3 because the first byte is also included
-            let byte = data[i + 1];
+/// # Fallible ICNS PackBits(like) decompression
+/// Same as `decompress`, but returns an error with the offset of the
+/// offending run instead of silently stopping on malformed data.
+///
+/// The optional limit is the maximum amount of decompressed bytes.
+/// Because a 2 byte run can expand to 130 bytes, pass the expected
+/// length of the output when decompressing untrusted data.
+///
+/// ```rust
+/// use icns_rs::packbits::{try_decompress, PackBitsError};
+///
+/// // The literal run announces 3 bytes but only 2 follow
+/// assert_eq!(
+///     try_decompress(&[0x02, 0x01, 0x02], None),
+///     Err(PackBitsError::Truncated { offset: 0 })
+/// );
+///
+/// // The repeated run expands to 130 bytes
+/// assert_eq!(
+///     try_decompress(&[0xFF, 0x01], Some(16)),
+///     Err(PackBitsError::LimitExceeded {
+///         offset: 0,
+///         limit: 16
+///     })
+/// );
+/// ```
+pub fn try_decompress(data: &[u8], limit: Option<usize>) -> Result<Box<[u8]>, PackBitsError> {
+    let mut buffer = Vec::with_capacity(limit.unwrap_or(0));
+    decompress_into(data, &mut buffer, limit)?;
 
-            let mut buffer = Vec::with_capacity(repeats as usize);
-            for _ in 0..repeats {
-                buffer.push(byte);
+    Ok(buffer.into_boxed_slice())
+}
+
+/// # Decompressing into a buffer
+/// Same as `try_decompress`, but appends the decompressed data to the
+/// buffer. The limit only counts the appended bytes. On error the
+/// runs before the offset have already been appended.
+///
+/// ```rust
+/// let mut buffer = Vec::with_capacity(6);
+///
+/// icns_rs::packbits::decompress_into(&[0x80, 0x01], &mut buffer, Some(6)).unwrap();
+/// icns_rs::packbits::decompress_into(&[0x02, 0x01, 0x02, 0x03], &mut buffer, Some(3)).unwrap();
+///
+/// assert_eq!(buffer, vec![0x01, 0x01, 0x01, 0x01, 0x02, 0x03]);
+/// ```
+pub fn decompress_into(
+    data: &[u8],
+    buffer: &mut Vec<u8>,
+    limit: Option<usize>,
+) -> Result<(), PackBitsError> {
+    let start = buffer.len();
+    let mut offset = 0;
+
+    for run in runs(data) {
+        let run = run?;
+
+        if let Some(limit) = limit {
+            if buffer.len() - start + run.len() > limit {
+                return Err(PackBitsError::LimitExceeded { offset, limit });
             }
+        }
 
-            buffers.push(buffer.into_boxed_slice());
+        match run {
+            Run::Literal(bytes) => {
+                buffer.extend_from_slice(bytes);
+                offset += bytes.len() + 1;
+            }
+            Run::Repeat { byte, count } => {
+                buffer.resize(buffer.len() + count, byte);
+                offset += 2;
+            }
+        }
+    }
 
-            i += 2; // Compressed bytes are always 2 bytes long
-        } else {
-            // Not compressed
-            let length = data[i] as usize + 1;
-            let mut buffer = Vec::with_capacity(length);
-            buffer.extend_from_slice(&data[i + 1..i + length + 1]);
+    Ok(())
+}
 
-            buffers.push(buffer.into_boxed_slice());
+/// # Runs
+/// Iterates over the runs of compressed data without copying them.
+/// After a truncated run the iterator returns the error and stops.
+///
+/// ```rust
+/// use icns_rs::packbits::{runs, Run};
+///
+/// let runs = runs(&[0x01, 0x01, 0x02, 0x81, 0x03])
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+///
+/// assert_eq!(
+///     runs,
+///     vec![Run::Literal(&[0x01, 0x02]), Run::Repeat { byte: 0x03, count: 4 }]
+/// );
+/// ```
+pub fn runs(data: &[u8]) -> Runs<'_> {
+    Runs { data, offset: 0 }
+}
+
+/// A run of compressed data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Run<'a> {
+    /// Bytes that are stored as they are
+    Literal(&'a [u8]),
+    /// A byte repeated `count` times
+    Repeat { byte: u8, count: usize },
+}
 
-            i += length + 1;
+impl Run<'_> {
+    /// Returns the amount of decompressed bytes
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Run::Literal(bytes) => bytes.len(),
+            Run::Repeat { count, .. } => *count,
         }
     }
+}
 
-    // Compact the buffers into a single buffer
-    let mut buffer = Vec::with_capacity(buffers.iter().map(|b| b.len()).sum());
-    for b in buffers {
-        buffer.extend_from_slice(&b);
+/// The iterator returned by `runs`
+#[derive(Debug, Clone)]
+pub struct Runs<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for Runs<'a> {
+    type Item = Result<Run<'a>, PackBitsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let header = *self.data.get(offset)?;
+
+        // We know it's compressed if the first byte is greater or equal to 128
+        let (run, run_length) = if header >= ENCODE_REPEAT {
+            // How many times the byte is repeated
+            // + 3 because the first byte is also included
+            // Compressed bytes are always 2 bytes long
+            let run = self.data.get(offset + 1).map(|&byte| Run::Repeat {
+                byte,
+                count: header as usize - ENCODE_REPEAT as usize + 3,
+            });
+
+            (run, 2)
+        } else {
+            // Not compressed
+            let length = header as usize + 1;
+            let run = self
+                .data
+                .get(offset + 1..offset + 1 + length)
+                .map(Run::Literal);
+
+            (run, length + 1)
+        };
+
+        match run {
+            Some(run) => {
+                self.offset += run_length;
+                Some(Ok(run))
+            }
+            None => {
+                self.offset = self.data.len();
+                Some(Err(PackBitsError::Truncated { offset }))
+            }
+        }
     }
+}
 
-    buffer.into_boxed_slice()
+impl std::iter::FusedIterator for Runs<'_> {}
+
+/// # PackBits errors
+/// The errors that can occur while decompressing PackBits data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PackBitsError {
+    /// The run starting at the offset is missing some of its bytes
+    Truncated { offset: usize },
+    /// The run starting at the offset would exceed the output limit
+    LimitExceeded { offset: usize, limit: usize },
 }
 
+impl fmt::Display for PackBitsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackBitsError::Truncated { offset } => {
+                write!(f, "PackBits run at offset {} is truncated", offset)
+            }
+            PackBitsError::LimitExceeded { offset, limit } => write!(
+                f,
+                "PackBits run at offset {} exceeds the limit of {} bytes",
+                offset, limit
+            ),
+        }
+    }
+}
+
+impl Error for PackBitsError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,4 +586,168 @@ mod tests {
             STRESS_NO_REPEAT_RAW.to_vec().into_boxed_slice()
         );
     }
+
+    #[test]
+    fn try_decompress_malformed() {
+        // Repeated run without the byte
+        assert_eq!(
+            try_decompress(&[0x02, 0x01, 0x02, 0x03, 0x80], None),
+            Err(PackBitsError::Truncated { offset: 4 })
+        );
+        // Literal run without enough bytes
+        assert_eq!(
+            try_decompress(&[0x80, 0x01, 0x05, 0x01], None),
+            Err(PackBitsError::Truncated { offset: 2 })
+        );
+        // Limit
+        assert_eq!(
+            try_decompress(&BASIC_COMPRESSED, Some(BASIC_RAW.len())),
+            Ok(BASIC_RAW.to_vec().into_boxed_slice())
+        );
+        assert_eq!(
+            try_decompress(&BASIC_COMPRESSED, Some(BASIC_RAW.len() - 1)),
+            Err(PackBitsError::LimitExceeded {
+                offset: 8,
+                limit: BASIC_RAW.len() - 1
+            })
+        );
+    }
+
+    #[test]
+    fn compress_optimal_round_trip() {
+        // Pseudo random data with runs of different lengths
+        let mut state = 0x1234_5678u32;
+        let mut data = Vec::new();
+        for _ in 0..500 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let run = (state >> 16) as usize % 7 + 1;
+            data.extend(std::iter::repeat((state >> 8) as u8 % 4).take(run));
+        }
+
+        for raw in [
+            &data[..],
+            &BASIC_RAW,
+            &STRESS_REPEAT_RAW,
+            &STRESS_NO_REPEAT_RAW,
+            &[0x01; 262],
+            &[],
+        ] {
+            let compressed = compress_optimal(raw);
+
+            assert!(compressed.len() <= compress(raw.into()).len());
+            assert_eq!(&*decompress(compressed), raw);
+        }
+    }
+
+    #[test]
+    fn compress_optimal_smaller() {
+        // The greedy encoder adds the trailing repeated bytes
+        // to the literal run instead of starting a repeated run
+        let raw = [0x01, 0x02, 0x02, 0x02];
+
+        assert_eq!(
+            compress(raw.into()),
+            vec![0x03, 0x01, 0x02, 0x02, 0x02].into_boxed_slice()
+        );
+        assert_eq!(
+            compress_optimal(&raw),
+            vec![0x00, 0x01, 0x80, 0x02].into_boxed_slice()
+        );
+    }
+
+    /// Pseudo random data with runs of every length the runs can have
+    fn runs_data() -> Vec<u8> {
+        let mut state = 0x8765_4321u32;
+        let mut data = Vec::new();
+        for _ in 0..400 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let run = (state >> 16) as usize % 300 / ((state >> 4) as usize % 40 + 1) + 1;
+            data.extend(std::iter::repeat((state >> 8) as u8 % 3).take(run));
+        }
+
+        data
+    }
+
+    #[test]
+    fn compress_into_and_iter() {
+        let data = runs_data();
+
+        for length in (0..data.len()).step_by(97).chain([data.len()]) {
+            let raw = &data[..length];
+            let compressed = compress(raw.into());
+
+            // Appends to the buffer
+            let mut buffer = vec![0xAA];
+            compress_into(raw, &mut buffer);
+            assert_eq!(buffer[0], 0xAA);
+            assert_eq!(&buffer[1..], &*compressed);
+
+            let mut buffer = Vec::new();
+            compress_iter(raw.iter().copied(), &mut buffer);
+            assert_eq!(&*buffer, &*compressed);
+
+            let mut buffer = Vec::new();
+            compress_with_into(raw, Compression::Optimal, &mut buffer);
+            assert_eq!(&*buffer, &*compress_optimal(raw));
+        }
+    }
+
+    #[test]
+    fn decompress_into_and_runs() {
+        let data = runs_data();
+        let compressed = compress(data.as_slice().into());
+
+        let mut buffer = vec![0xAA];
+        decompress_into(&compressed, &mut buffer, Some(data.len())).unwrap();
+        assert_eq!(buffer[0], 0xAA);
+        assert_eq!(&buffer[1..], &*data);
+
+        // The limit only counts the appended bytes
+        assert!(matches!(
+            decompress_into(&compressed, &mut buffer, Some(data.len() - 1)),
+            Err(PackBitsError::LimitExceeded { .. })
+        ));
+
+        assert_eq!(
+            runs(&BASIC_COMPRESSED).collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                Run::Literal(&[0x01, 0x02, 0x02]),
+                Run::Repeat {
+                    byte: 0x03,
+                    count: 3
+                },
+                Run::Repeat {
+                    byte: 0x04,
+                    count: 4
+                },
+                Run::Repeat {
+                    byte: 0x05,
+                    count: 5
+                },
+            ])
+        );
+
+        // Stops after the error
+        let mut malformed = runs(&[0x80, 0x01, 0x05, 0x01]);
+        assert_eq!(
+            malformed.next(),
+            Some(Ok(Run::Repeat {
+                byte: 0x01,
+                count: 3
+            }))
+        );
+        assert_eq!(
+            malformed.next(),
+            Some(Err(PackBitsError::Truncated { offset: 2 }))
+        );
+        assert_eq!(malformed.next(), None);
+    }
+
+    #[test]
+    fn decompress_malformed() {
+        assert_eq!(
+            decompress(vec![0x80, 0x01, 0x05, 0x01].into_boxed_slice()),
+            vec![0x01, 0x01, 0x01].into_boxed_slice()
+        );
+    }
 }
@@ -0,0 +1,157 @@
+/// # Mac OS 16 color palette
+/// The fixed system palette used by the 4-bit icon types
+/// (icl4, ics4, icm4, ich4).
+pub const MAC_16: [[u8; 3]; 16] = [
+    [0xFF, 0xFF, 0xFF], // White
+    [0xFC, 0xF3, 0x05], // Yellow
+    [0xFF, 0x64, 0x02], // Orange
+    [0xDD, 0x08, 0x06], // Red
+    [0xF2, 0x08, 0x84], // Magenta
+    [0x46, 0x00, 0xA5], // Purple
+    [0x00, 0x00, 0xD4], // Blue
+    [0x02, 0xAB, 0xEA], // Cyan
+    [0x1F, 0xB7, 0x14], // Green
+    [0x00, 0x64, 0x11], // Dark green
+    [0x56, 0x2C, 0x05], // Brown
+    [0x90, 0x71, 0x3A], // Tan
+    [0xC0, 0xC0, 0xC0], // Light gray
+    [0x80, 0x80, 0x80], // Medium gray
+    [0x40, 0x40, 0x40], // Dark gray
+    [0x00, 0x00, 0x00], // Black
+];
+
+/// # Mac OS 256 color palette
+/// The fixed system palette used by the 8-bit icon types
+/// (icl8, ics8, icm8, ich8).
+/// - 0 - 214: 6x6x6 color cube from white down (without black)
+/// - 215 - 224: Red ramp
+/// - 225 - 234: Green ramp
+/// - 235 - 244: Blue ramp
+/// - 245 - 254: Gray ramp
+/// - 255: Black
+pub const MAC_256: [[u8; 3]; 256] = mac_256();
+
+const fn mac_256() -> [[u8; 3]; 256] {
+    const CUBE: [u8; 6] = [0xFF, 0xCC, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xEE, 0xDD, 0xBB, 0xAA, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = [[0x00; 3]; 256];
+
+    // The last color of the cube is black, which is stored at the end
+    let mut i = 0;
+    while i < 215 {
+        palette[i] = [CUBE[i / 36], CUBE[i / 6 % 6], CUBE[i % 6]];
+        i += 1;
+    }
+
+    let mut j = 0;
+    while j < 10 {
+        palette[215 + j] = [RAMP[j], 0x00, 0x00];
+        palette[225 + j] = [0x00, RAMP[j], 0x00];
+        palette[235 + j] = [0x00, 0x00, RAMP[j]];
+        palette[245 + j] = [RAMP[j], RAMP[j], RAMP[j]];
+        j += 1;
+    }
+
+    palette
+}
+
+/// Returns the index of the palette color closest to the color
+pub fn nearest(palette: &[[u8; 3]], color: [i16; 3]) -> u8 {
+    let mut best = 0;
+    let mut best_distance = i32::MAX;
+
+    for (i, entry) in palette.iter().enumerate() {
+        let distance = (0..3)
+            .map(|c| {
+                let delta = color[c] as i32 - entry[c] as i32;
+                delta * delta
+            })
+            .sum::<i32>();
+
+        if distance < best_distance {
+            best = i;
+            best_distance = distance;
+        }
+    }
+
+    best as u8
+}
+
+/// # Quantizing an image
+/// Maps every RGB pixel of an image to the index of a palette color.
+/// With `dither` the error of every pixel is spread to its neighbours
+/// (Floyd–Steinberg), otherwise the nearest color is used.
+pub fn quantize(pixels: &[[u8; 3]], width: usize, palette: &[[u8; 3]], dither: bool) -> Vec<u8> {
+    let height = pixels.len() / width;
+    let mut colors = pixels
+        .iter()
+        .map(|pixel| [pixel[0] as i16, pixel[1] as i16, pixel[2] as i16])
+        .collect::<Vec<_>>();
+    let mut indices = Vec::with_capacity(pixels.len());
+
+    for i in 0..colors.len() {
+        let color = colors[i].map(|c| c.clamp(0x00, 0xFF));
+        let index = nearest(palette, color);
+        indices.push(index);
+
+        if !dither {
+            continue;
+        }
+
+        // Spread the error to the neighbouring pixels
+        let chosen = palette[index as usize];
+        let (x, y) = (i % width, i / width);
+
+        for c in 0..3 {
+            let error = color[c] - chosen[c] as i16;
+
+            if x + 1 < width {
+                colors[i + 1][c] += error * 7 / 16;
+            }
+            if y + 1 < height {
+                if x > 0 {
+                    colors[i + width - 1][c] += error * 3 / 16;
+                }
+                colors[i + width][c] += error * 5 / 16;
+                if x + 1 < width {
+                    colors[i + width + 1][c] += error / 16;
+                }
+            }
+        }
+    }
+
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mac_256_palette() {
+        assert_eq!(MAC_256[0], [0xFF, 0xFF, 0xFF]);
+        assert_eq!(MAC_256[1], [0xFF, 0xFF, 0xCC]);
+        assert_eq!(MAC_256[214], [0x00, 0x00, 0x33]);
+        assert_eq!(MAC_256[215], [0xEE, 0x00, 0x00]);
+        assert_eq!(MAC_256[254], [0x11, 0x11, 0x11]);
+        assert_eq!(MAC_256[255], [0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn quantize_nearest() {
+        let pixels = [[0xFE, 0xFE, 0xFE], [0x01, 0x02, 0x03], [0xDE, 0x10, 0x00]];
+
+        assert_eq!(quantize(&pixels, 3, &MAC_16, false), vec![0, 15, 3]);
+    }
+
+    #[test]
+    fn quantize_dither() {
+        // A medium gray that is not in the palette alternates between colors
+        let pixels = [[0x60, 0x60, 0x60]; 64];
+        let indices = quantize(&pixels, 8, &MAC_16, true);
+
+        assert!(indices.contains(&13));
+        assert!(indices.contains(&14));
+    }
+}
@@ -0,0 +1,554 @@
+use crate::{
+    encode::IcnsDataEntry,
+    error::IcnsError,
+    formats::{FileFormat, IconFormats},
+    packbits, palette,
+};
+
+use image::{DynamicImage, GrayAlphaImage, GrayImage, ImageFormat, RgbImage, RgbaImage};
+
+pub(crate) const PNG_MAGIC: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+pub(crate) const ARGB_MAGIC: [u8; 4] = [0x41, 0x52, 0x47, 0x42]; // "ARGB"
+
+/// Returns the first 4 bytes of the data (padded with zeros) for errors
+fn first_bytes(data: &[u8]) -> [u8; 4] {
+    let mut bytes = [0x00; 4];
+    for (byte, value) in bytes.iter_mut().zip(data) {
+        *byte = *value;
+    }
+
+    bytes
+}
+
+/// The ImageParser struct
+/// This struct is the counterpart of the `ImageBuilder`.
+/// It takes an encoded `IcnsDataEntry` and decodes the RGB,
+/// ARGB, mask, PNG, 1-bit mono, or palette data back into an image
+pub struct ImageParser {
+    pub format: IconFormats,
+    pub data: IcnsDataEntry,
+    pub mask: Option<IcnsDataEntry>,
+}
+
+impl Default for ImageParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ImageParser {
+    pub fn new() -> Self {
+        Self {
+            format: IconFormats::IS32,
+            data: IcnsDataEntry::new(IconFormats::IS32.get_bytes(), Vec::new().into_boxed_slice()),
+            mask: None,
+        }
+    }
+
+    /// Sets the image format
+    /// See the `IconFormats` enum for more information
+    pub fn format(&mut self, format: IconFormats) -> &mut Self {
+        self.format = format;
+
+        self
+    }
+
+    /// Sets the entry to be decoded
+    pub fn data(&mut self, data: IcnsDataEntry) -> &mut Self {
+        self.data = data;
+
+        self
+    }
+
+    /// Sets the mask entry that holds the alpha of an RGB or palette image
+    /// This is either an 8-bit mask or a 1-bit mono icon with its mask
+    /// See `IconFormats::get_mask` for the mask of each type
+    pub fn mask(&mut self, mask: Option<IcnsDataEntry>) -> &mut Self {
+        self.mask = mask;
+
+        self
+    }
+
+    /// Decodes a PackBits compressed RGB image
+    /// If a mask is set, it is used as the alpha channel
+    /// You probably want to use `.parse()` instead of this method
+    pub fn rgb_image(&self) -> Result<DynamicImage, IcnsError> {
+        let size = self.format.get_size() as u32;
+        let pixels = (size * size) as usize;
+
+        // Skip the offset if the type is it32
+        let data = if self.format == IconFormats::IT32 {
+            if self.data.data.len() < 4 {
+                return Err(IcnsError::TruncatedData {
+                    offset: self.data.data.len(),
+                });
+            }
+
+            &self.data.data[4..]
+        } else {
+            &self.data.data[..]
+        };
+
+        let channels = packbits::try_decompress(data, Some(pixels * 3))?;
+        if channels.len() != pixels * 3 {
+            return Err(IcnsError::LengthMismatch {
+                expected: pixels * 3,
+                actual: channels.len(),
+            });
+        }
+
+        // The channels are stored one after another (RRR...GGG...BBB...)
+        let colors = (0..pixels)
+            .map(|i| [channels[i], channels[pixels + i], channels[pixels * 2 + i]])
+            .collect::<Vec<_>>();
+
+        self.colors_image(size, size, colors)
+    }
+
+    /// Decodes a 4-bit or 8-bit palette image
+    /// If a mask is set, it is used as the alpha channel
+    /// You probably want to use `.parse()` instead of this method
+    pub fn palette_image(&self) -> Result<DynamicImage, IcnsError> {
+        let (width, height) = self.format.get_dimensions();
+        let pixels = width * height;
+
+        let (palette, bits): (&[[u8; 3]], usize) = match self.format.get_format() {
+            FileFormat::PALETTE4 => (&palette::MAC_16, 4),
+            _ => (&palette::MAC_256, 8),
+        };
+
+        if self.data.data.len() != pixels * bits / 8 {
+            return Err(IcnsError::LengthMismatch {
+                expected: pixels * bits / 8,
+                actual: self.data.data.len(),
+            });
+        }
+
+        // The 4-bit types store two pixels per byte (high nibble first)
+        let colors = (0..pixels)
+            .map(|i| {
+                let index = if bits == 4 {
+                    (self.data.data[i / 2] >> (4 - i % 2 * 4)) & 0x0F
+                } else {
+                    self.data.data[i]
+                };
+
+                palette[index as usize]
+            })
+            .collect::<Vec<_>>();
+
+        self.colors_image(width as u32, height as u32, colors)
+    }
+
+    /// Decodes a PackBits compressed ARGB image
+    /// You probably want to use `.parse()` instead of this method
+    pub fn argb_image(&self) -> Result<DynamicImage, IcnsError> {
+        let size = self.format.get_size() as u32;
+        let pixels = (size * size) as usize;
+
+        // Some encoders store PNG data in the ARGB types
+        if self.data.data.starts_with(&PNG_MAGIC) {
+            return self.png_image();
+        }
+
+        // File header
+        if !self.data.data.starts_with(&ARGB_MAGIC) {
+            return Err(IcnsError::BadMagic(first_bytes(&self.data.data)));
+        }
+
+        let channels = packbits::try_decompress(&self.data.data[4..], Some(pixels * 4))?;
+        if channels.len() != pixels * 4 {
+            return Err(IcnsError::LengthMismatch {
+                expected: pixels * 4,
+                actual: channels.len(),
+            });
+        }
+
+        // The channels are stored one after another (AAA...RRR...GGG...BBB...)
+        let mut buffer = Vec::with_capacity(pixels * 4);
+        for i in 0..pixels {
+            buffer.push(channels[pixels + i]);
+            buffer.push(channels[pixels * 2 + i]);
+            buffer.push(channels[pixels * 3 + i]);
+            buffer.push(channels[i]);
+        }
+
+        Ok(DynamicImage::ImageRgba8(
+            RgbaImage::from_raw(size, size, buffer).unwrap(),
+        ))
+    }
+
+    /// Decodes an uncompressed 8-bit mask as a grayscale image
+    /// You probably want to use `.parse()` instead of this method
+    pub fn mask_image(&self) -> Result<DynamicImage, IcnsError> {
+        let size = self.format.get_size() as u32;
+
+        match GrayImage::from_raw(size, size, self.data.data.to_vec()) {
+            Some(image) => Ok(DynamicImage::ImageLuma8(image)),
+            None => Err(IcnsError::LengthMismatch {
+                expected: (size * size) as usize,
+                actual: self.data.data.len(),
+            }),
+        }
+    }
+
+    /// Decodes a 1-bit mono icon and its 1-bit mask
+    /// as a grayscale image with alpha
+    /// You probably want to use `.parse()` instead of this method
+    pub fn mono_image(&self) -> Result<DynamicImage, IcnsError> {
+        let (width, height) = self.format.get_dimensions();
+        let pixels = width * height;
+
+        // The icon is followed by the mask, both use one bit per pixel
+        if self.data.data.len() != pixels / 4 {
+            return Err(IcnsError::LengthMismatch {
+                expected: pixels / 4,
+                actual: self.data.data.len(),
+            });
+        }
+
+        let (icon, mask) = self.data.data.split_at(pixels / 8);
+
+        let mut buffer = Vec::with_capacity(pixels * 2);
+        for i in 0..pixels {
+            let bit = 0x80 >> (i % 8);
+
+            buffer.push(if icon[i / 8] & bit != 0 { 0x00 } else { 0xFF });
+            buffer.push(if mask[i / 8] & bit != 0 { 0xFF } else { 0x00 });
+        }
+
+        Ok(DynamicImage::ImageLumaA8(
+            GrayAlphaImage::from_raw(width as u32, height as u32, buffer).unwrap(),
+        ))
+    }
+
+    /// Decodes an embedded PNG
+    /// Other embedded formats (e.g. JPEG 2000) are not supported
+    pub fn png_image(&self) -> Result<DynamicImage, IcnsError> {
+        if !self.data.data.starts_with(&PNG_MAGIC) {
+            return Err(IcnsError::UnsupportedFormat(first_bytes(&self.data.data)));
+        }
+
+        Ok(image::load_from_memory_with_format(
+            &self.data.data,
+            ImageFormat::Png,
+        )?)
+    }
+
+    /// Decodes the mask as one alpha value per pixel
+    fn alpha(&self, pixels: usize) -> Result<Option<Vec<u8>>, IcnsError> {
+        let mask = match &self.mask {
+            Some(mask) => mask,
+            None => return Ok(None),
+        };
+
+        let format = IconFormats::from_bytes(mask.os_type).map(|format| format.get_format());
+        if format == Some(FileFormat::MONO) {
+            // The 1-bit mask follows the 1-bit icon
+            if mask.data.len() != pixels / 4 {
+                return Err(IcnsError::LengthMismatch {
+                    expected: pixels / 4,
+                    actual: mask.data.len(),
+                });
+            }
+
+            let bits = &mask.data[pixels / 8..];
+
+            return Ok(Some(
+                (0..pixels)
+                    .map(|i| {
+                        if bits[i / 8] & (0x80 >> (i % 8)) != 0 {
+                            0xFF
+                        } else {
+                            0x00
+                        }
+                    })
+                    .collect(),
+            ));
+        }
+
+        if mask.data.len() != pixels {
+            return Err(IcnsError::LengthMismatch {
+                expected: pixels,
+                actual: mask.data.len(),
+            });
+        }
+
+        Ok(Some(mask.data.to_vec()))
+    }
+
+    /// Creates an RGB image from the colors, or an RGBA image if a mask is set
+    fn colors_image(
+        &self,
+        width: u32,
+        height: u32,
+        colors: Vec<[u8; 3]>,
+    ) -> Result<DynamicImage, IcnsError> {
+        if let Some(alpha) = self.alpha(colors.len())? {
+            let buffer = colors
+                .iter()
+                .zip(alpha)
+                .flat_map(|(color, alpha)| [color[0], color[1], color[2], alpha])
+                .collect::<Vec<_>>();
+
+            return Ok(DynamicImage::ImageRgba8(
+                RgbaImage::from_raw(width, height, buffer).unwrap(),
+            ));
+        }
+
+        Ok(DynamicImage::ImageRgb8(
+            RgbImage::from_raw(width, height, colors.concat()).unwrap(),
+        ))
+    }
+
+    pub fn parse(&self) -> Result<DynamicImage, IcnsError> {
+        match self.format.get_format() {
+            FileFormat::RGB => self.rgb_image(),
+            FileFormat::ARGB => self.argb_image(),
+            FileFormat::MASK => self.mask_image(),
+            FileFormat::PNG => self.png_image(),
+            FileFormat::MONO => self.mono_image(),
+            FileFormat::PALETTE4 => self.palette_image(),
+            FileFormat::PALETTE8 => self.palette_image(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ImageBuilder;
+
+    use image::{GenericImageView, Rgba};
+
+    fn source() -> DynamicImage {
+        let mut image = RgbaImage::new(32, 32);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            *pixel = Rgba([
+                (x * 8) as u8,
+                (y * 8) as u8,
+                0x80,
+                if x < 16 { 0xFF } else { 0x00 },
+            ]);
+        }
+
+        DynamicImage::ImageRgba8(image)
+    }
+
+    fn round_trip(format: IconFormats) -> DynamicImage {
+        let mut builder = ImageBuilder::new();
+        let entry = builder.data(source()).format(format).build().unwrap();
+
+        ImageParser::new()
+            .format(format)
+            .data(entry)
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_rgb() {
+        let image = round_trip(IconFormats::IL32);
+
+        assert_eq!(image.to_rgb8(), source().to_rgb8());
+    }
+
+    #[test]
+    fn parse_it32() {
+        let image = round_trip(IconFormats::IT32);
+
+        assert_eq!(image.dimensions(), (128, 128));
+    }
+
+    #[test]
+    fn parse_rgb_with_mask() {
+        let mut builder = ImageBuilder::new();
+        builder.data(source());
+
+        let rgb = builder.format(IconFormats::IL32).build().unwrap();
+        let mask = builder.format(IconFormats::L8MK).build().unwrap();
+
+        let image = ImageParser::new()
+            .format(IconFormats::IL32)
+            .data(rgb)
+            .mask(Some(mask))
+            .parse()
+            .unwrap();
+
+        assert_eq!(image.to_rgba8(), source().to_rgba8());
+    }
+
+    #[test]
+    fn parse_argb() {
+        let image = round_trip(IconFormats::IC05);
+
+        assert_eq!(image.to_rgba8(), source().to_rgba8());
+    }
+
+    #[test]
+    fn parse_mask() {
+        let image = round_trip(IconFormats::L8MK);
+
+        assert_eq!(image.get_pixel(0, 0), Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
+        assert_eq!(image.get_pixel(31, 0), Rgba([0x00, 0x00, 0x00, 0xFF]));
+    }
+
+    #[test]
+    fn parse_png() {
+        let image = round_trip(IconFormats::IC11);
+
+        assert_eq!(image.to_rgba8(), source().to_rgba8());
+    }
+
+    #[test]
+    fn parse_mono() {
+        let image = round_trip(IconFormats::ICN).to_luma_alpha8();
+
+        // Opaque and dark
+        assert_eq!(image.get_pixel(0, 0).0, [0x00, 0xFF]);
+        // Opaque and light
+        assert_eq!(image.get_pixel(15, 31).0, [0xFF, 0xFF]);
+        // Transparent
+        assert_eq!(image.get_pixel(31, 31).0, [0xFF, 0x00]);
+    }
+
+    #[test]
+    fn parse_mono_mini() {
+        let mut builder = ImageBuilder::new();
+        let entry = builder
+            .data(source())
+            .format(IconFormats::ICM)
+            .dither(true)
+            .build()
+            .unwrap();
+
+        // 16x12 icon and mask
+        assert_eq!(entry.data.len(), 48);
+
+        let image = ImageParser::new()
+            .format(IconFormats::ICM)
+            .data(entry)
+            .parse()
+            .unwrap();
+
+        assert_eq!(image.dimensions(), (16, 12));
+    }
+
+    fn palette_source(palette: &[[u8; 3]]) -> DynamicImage {
+        let mut image = RgbaImage::new(32, 32);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let color = palette[((x + y * 32) as usize) % palette.len()];
+            *pixel = Rgba([color[0], color[1], color[2], 0xFF]);
+        }
+
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn parse_palette() {
+        for (format, palette) in [
+            (IconFormats::ICL4, &palette::MAC_16[..]),
+            (IconFormats::ICL8, &palette::MAC_256[..]),
+        ] {
+            let mut builder = ImageBuilder::new();
+            let entry = builder
+                .data(palette_source(palette))
+                .format(format)
+                .build()
+                .unwrap();
+
+            let image = ImageParser::new()
+                .format(format)
+                .data(entry)
+                .parse()
+                .unwrap();
+
+            assert_eq!(image.to_rgb8(), palette_source(palette).to_rgb8());
+        }
+    }
+
+    #[test]
+    fn parse_palette_with_mono_mask() {
+        let mut builder = ImageBuilder::new();
+        builder.data(source()).dither(true);
+
+        let icon = builder.format(IconFormats::ICM8).build().unwrap();
+        let mask = builder.format(IconFormats::ICM).build().unwrap();
+
+        // 16x12 pixels, one byte each
+        assert_eq!(icon.data.len(), 192);
+
+        let image = ImageParser::new()
+            .format(IconFormats::ICM8)
+            .data(icon)
+            .mask(Some(mask))
+            .parse()
+            .unwrap()
+            .to_rgba8();
+
+        assert_eq!(image.dimensions(), (16, 12));
+        assert_eq!(image.get_pixel(0, 0).0[3], 0xFF);
+        assert_eq!(image.get_pixel(15, 0).0[3], 0x00);
+    }
+
+    #[test]
+    fn parse_example_file() {
+        let family = crate::encode::IconFamily::parse(include_bytes!("../example.icns")).unwrap();
+
+        for entry in family.data {
+            if let Some(format) = IconFormats::from_bytes(entry.os_type) {
+                let (width, height) = format.get_dimensions();
+                let image = ImageParser::new()
+                    .format(format)
+                    .data(entry)
+                    .parse()
+                    .unwrap();
+
+                assert_eq!(image.dimensions(), (width as u32, height as u32));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_invalid_data() {
+        let entry = IcnsDataEntry::new(
+            IconFormats::IC04.get_bytes(),
+            vec![0x00, 0x01].into_boxed_slice(),
+        );
+
+        assert!(ImageParser::new()
+            .format(IconFormats::IC04)
+            .data(entry)
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_malformed_packbits() {
+        // Truncated literal run
+        let entry = IcnsDataEntry::new(
+            IconFormats::IS32.get_bytes(),
+            vec![0x7F, 0x00].into_boxed_slice(),
+        );
+        assert!(matches!(
+            ImageParser::new()
+                .format(IconFormats::IS32)
+                .data(entry)
+                .parse(),
+            Err(IcnsError::PackBits(_))
+        ));
+
+        // Decompression bomb
+        let entry = IcnsDataEntry::new(
+            IconFormats::IS32.get_bytes(),
+            [0xFF, 0x00].repeat(1024).into_boxed_slice(),
+        );
+        assert!(matches!(
+            ImageParser::new()
+                .format(IconFormats::IS32)
+                .data(entry)
+                .parse(),
+            Err(IcnsError::PackBits(_))
+        ));
+    }
+}
@@ -0,0 +1,707 @@
+use std::{cell::Cell, collections::BTreeMap};
+
+use crate::error::IcnsError;
+
+const XML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+<plist version=\"1.0\">\n";
+const BINARY_MAGIC: &[u8; 8] = b"bplist00";
+/// The maximum nesting of arrays and dictionaries
+const MAX_DEPTH: usize = 64;
+/// The maximum number of objects decoded from a binary plist. Shared
+/// objects are decoded every time they are referenced, so without a
+/// limit a small plist could take exponential time to decode.
+const MAX_OBJECTS: usize = 1 << 16;
+
+/// A plist dictionary, the keys are sorted when serialized
+pub type Dictionary = BTreeMap<String, Value>;
+
+/// # Property list values
+/// A minimal property list implementation used for the `info` entry.
+/// XML property lists can be written and both XML and binary property
+/// lists can be read. Dates are read as strings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    Data(Vec<u8>),
+    Array(Vec<Value>),
+    Dictionary(Dictionary),
+}
+
+impl Value {
+    /// Returns the string if the value is a string
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the dictionary if the value is a dictionary
+    pub fn as_dictionary(&self) -> Option<&Dictionary> {
+        match self {
+            Value::Dictionary(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// ## Serializing
+    /// Serializes the value as an XML property list.
+    pub fn to_xml(&self) -> String {
+        let mut buffer = String::from(XML_HEADER);
+        self.write_xml(&mut buffer, 0);
+        buffer.push_str("</plist>\n");
+
+        buffer
+    }
+
+    fn write_xml(&self, buffer: &mut String, depth: usize) {
+        let indent = "\t".repeat(depth);
+
+        match self {
+            Value::String(value) => {
+                buffer.push_str(&format!("{}<string>{}</string>\n", indent, escape(value)))
+            }
+            Value::Integer(value) => {
+                buffer.push_str(&format!("{}<integer>{}</integer>\n", indent, value))
+            }
+            Value::Real(value) => buffer.push_str(&format!("{}<real>{}</real>\n", indent, value)),
+            Value::Boolean(true) => buffer.push_str(&format!("{}<true/>\n", indent)),
+            Value::Boolean(false) => buffer.push_str(&format!("{}<false/>\n", indent)),
+            Value::Data(value) => {
+                buffer.push_str(&format!("{}<data>{}</data>\n", indent, base64(value)))
+            }
+            Value::Array(values) => {
+                buffer.push_str(&format!("{}<array>\n", indent));
+                for value in values {
+                    value.write_xml(buffer, depth + 1);
+                }
+                buffer.push_str(&format!("{}</array>\n", indent));
+            }
+            Value::Dictionary(values) => {
+                buffer.push_str(&format!("{}<dict>\n", indent));
+                for (key, value) in values {
+                    buffer.push_str(&format!("{}\t<key>{}</key>\n", indent, escape(key)));
+                    value.write_xml(buffer, depth + 1);
+                }
+                buffer.push_str(&format!("{}</dict>\n", indent));
+            }
+        }
+    }
+
+    /// ## Parsing
+    /// Parses an XML or binary property list.
+    pub fn parse(data: &[u8]) -> Result<Value, IcnsError> {
+        if data.starts_with(BINARY_MAGIC) {
+            return BinaryParser::new(data)?.parse();
+        }
+
+        let text = match std::str::from_utf8(data) {
+            Ok(text) => text,
+            Err(e) => return Err(IcnsError::InvalidPlist(e.to_string())),
+        };
+
+        XmlParser::new(text).parse()
+    }
+}
+
+/// Escapes the XML special characters
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Reverts `escape` (and the other predefined entities)
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut buffer = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                buffer.push(BASE64[(bits >> (18 - i * 6) & 0x3F) as usize] as char);
+            } else {
+                buffer.push('=');
+            }
+        }
+    }
+
+    buffer
+}
+
+fn unbase64(value: &str) -> Result<Vec<u8>, IcnsError> {
+    let mut buffer = Vec::with_capacity(value.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for c in value.bytes() {
+        if c.is_ascii_whitespace() || c == b'=' {
+            continue;
+        }
+
+        let index = match BASE64.iter().position(|&b| b == c) {
+            Some(index) => index as u32,
+            None => return Err(IcnsError::InvalidPlist("Invalid base64 data".to_string())),
+        };
+
+        bits = bits << 6 | index;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            buffer.push((bits >> count) as u8);
+        }
+    }
+
+    Ok(buffer)
+}
+
+/// A minimal XML property list parser
+struct XmlParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> XmlParser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, position: 0 }
+    }
+
+    fn error(&self, message: &str) -> IcnsError {
+        IcnsError::InvalidPlist(format!("{} at offset {}", message, self.position))
+    }
+
+    fn parse(&mut self) -> Result<Value, IcnsError> {
+        // Skip the declaration, doctype and the plist tag
+        loop {
+            let tag = self.next_tag()?;
+            if tag.starts_with("plist") {
+                break;
+            }
+        }
+
+        let tag = self.next_tag()?;
+        self.value(&tag, 0)
+    }
+
+    /// Returns the next tag (without the brackets) and skips comments
+    fn next_tag(&mut self) -> Result<String, IcnsError> {
+        loop {
+            let start = match self.text[self.position..].find('<') {
+                Some(start) => self.position + start,
+                None => return Err(self.error("Unexpected end of data")),
+            };
+
+            if self.text[start..].starts_with("<!--") {
+                self.position = match self.text[start..].find("-->") {
+                    Some(end) => start + end + 3,
+                    None => return Err(self.error("Unterminated comment")),
+                };
+                continue;
+            }
+
+            let end = match self.text[start..].find('>') {
+                Some(end) => start + end,
+                None => return Err(self.error("Unterminated tag")),
+            };
+
+            self.position = end + 1;
+
+            return Ok(self.text[start + 1..end].trim().to_string());
+        }
+    }
+
+    /// Returns the text up to the closing tag
+    fn text_until(&mut self, tag: &str) -> Result<String, IcnsError> {
+        let closing = format!("</{}>", tag);
+
+        match self.text[self.position..].find(&closing) {
+            Some(end) => {
+                let text = unescape(&self.text[self.position..self.position + end]);
+                self.position += end + closing.len();
+
+                Ok(text)
+            }
+            None => Err(self.error("Missing closing tag")),
+        }
+    }
+
+    fn value(&mut self, tag: &str, depth: usize) -> Result<Value, IcnsError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("Plist is nested too deep"));
+        }
+
+        match tag {
+            "string" => Ok(Value::String(self.text_until("string")?)),
+            "string/" => Ok(Value::String(String::new())),
+            "date" => Ok(Value::String(self.text_until("date")?)),
+            "integer" => match self.text_until("integer")?.trim().parse() {
+                Ok(value) => Ok(Value::Integer(value)),
+                Err(_) => Err(self.error("Invalid integer")),
+            },
+            "real" => match self.text_until("real")?.trim().parse() {
+                Ok(value) => Ok(Value::Real(value)),
+                Err(_) => Err(self.error("Invalid real")),
+            },
+            "true/" => Ok(Value::Boolean(true)),
+            "false/" => Ok(Value::Boolean(false)),
+            "data" => Ok(Value::Data(unbase64(&self.text_until("data")?)?)),
+            "array/" => Ok(Value::Array(Vec::new())),
+            "array" => {
+                let mut values = Vec::new();
+                loop {
+                    let tag = self.next_tag()?;
+                    if tag == "/array" {
+                        return Ok(Value::Array(values));
+                    }
+
+                    values.push(self.value(&tag, depth + 1)?);
+                }
+            }
+            "dict/" => Ok(Value::Dictionary(Dictionary::new())),
+            "dict" => {
+                let mut values = Dictionary::new();
+                loop {
+                    let tag = self.next_tag()?;
+                    if tag == "/dict" {
+                        return Ok(Value::Dictionary(values));
+                    }
+                    if tag != "key" {
+                        return Err(self.error("Expected a key"));
+                    }
+
+                    let key = self.text_until("key")?;
+                    let tag = self.next_tag()?;
+                    values.insert(key, self.value(&tag, depth + 1)?);
+                }
+            }
+            _ => Err(self.error(&format!("Unexpected tag <{}>", tag))),
+        }
+    }
+}
+
+/// A minimal binary property list parser
+struct BinaryParser<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+    reference_size: usize,
+    root: usize,
+    decoded: Cell<usize>,
+}
+
+impl<'a> BinaryParser<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, IcnsError> {
+        // The trailer is the last 32 bytes
+        if data.len() < BINARY_MAGIC.len() + 32 {
+            return Err(IcnsError::TruncatedData { offset: data.len() });
+        }
+
+        let trailer = &data[data.len() - 32..];
+        let offset_size = trailer[6] as usize;
+        let reference_size = trailer[7] as usize;
+        let count = read_uint(&trailer[8..16]) as usize;
+        let root = read_uint(&trailer[16..24]) as usize;
+        let table = read_uint(&trailer[24..32]) as usize;
+
+        // Offsets and references are integers of 1 to 8 bytes
+        // An overflowing offset table is out of range as well
+        let table_end = count
+            .checked_mul(offset_size)
+            .and_then(|size| table.checked_add(size))
+            .unwrap_or(usize::MAX);
+        if !(1..=8).contains(&offset_size)
+            || !(1..=8).contains(&reference_size)
+            || table_end > data.len() - 32
+        {
+            return Err(IcnsError::InvalidPlist(
+                "Invalid binary plist trailer".to_string(),
+            ));
+        }
+
+        let offsets = (0..count)
+            .map(|i| read_uint(&data[table + i * offset_size..table + (i + 1) * offset_size]))
+            .map(|offset| offset as usize)
+            .collect();
+
+        Ok(Self {
+            data,
+            offsets,
+            reference_size,
+            root,
+            decoded: Cell::new(0),
+        })
+    }
+
+    fn parse(&self) -> Result<Value, IcnsError> {
+        self.object(self.root, &mut Vec::new())
+    }
+
+    fn bytes(&self, start: usize, length: usize) -> Result<&'a [u8], IcnsError> {
+        let end = checked(start.checked_add(length))?;
+
+        match self.data.get(start..end) {
+            Some(bytes) => Ok(bytes),
+            None => Err(IcnsError::TruncatedData { offset: start }),
+        }
+    }
+
+    /// Reads the length of an object, returns the length and where the data starts
+    fn length(&self, marker: u8, offset: usize) -> Result<(usize, usize), IcnsError> {
+        if marker & 0x0F != 0x0F {
+            return Ok(((marker & 0x0F) as usize, offset + 1));
+        }
+
+        // The length is stored as an integer object
+        let size = 1 << (self.bytes(offset + 1, 1)?[0] & 0x0F);
+        let length = read_uint(self.bytes(offset + 2, size)?) as usize;
+
+        Ok((length, offset + 2 + size))
+    }
+
+    fn references(&self, start: usize, count: usize) -> Result<Vec<usize>, IcnsError> {
+        let bytes = self.bytes(start, checked(count.checked_mul(self.reference_size))?)?;
+
+        Ok(bytes
+            .chunks(self.reference_size)
+            .map(|chunk| read_uint(chunk) as usize)
+            .collect())
+    }
+
+    /// Decodes the object at the index, `path` contains the objects
+    /// it's nested in
+    fn object(&self, index: usize, path: &mut Vec<usize>) -> Result<Value, IcnsError> {
+        if path.contains(&index) {
+            return Err(IcnsError::InvalidPlist(format!(
+                "Object {} references itself",
+                index
+            )));
+        }
+        if path.len() > MAX_DEPTH {
+            return Err(IcnsError::InvalidPlist(
+                "Plist is nested too deep".to_string(),
+            ));
+        }
+
+        self.decoded.set(self.decoded.get() + 1);
+        if self.decoded.get() > MAX_OBJECTS {
+            return Err(IcnsError::InvalidPlist(
+                "Plist has too many objects".to_string(),
+            ));
+        }
+
+        path.push(index);
+        let value = self.object_value(index, path);
+        path.pop();
+
+        value
+    }
+
+    fn object_value(&self, index: usize, path: &mut Vec<usize>) -> Result<Value, IcnsError> {
+        let offset = match self.offsets.get(index) {
+            Some(offset) => *offset,
+            None => return Err(IcnsError::InvalidPlist(format!("Invalid object {}", index))),
+        };
+        let marker = self.bytes(offset, 1)?[0];
+
+        match marker >> 4 {
+            0x0 => match marker {
+                0x08 => Ok(Value::Boolean(false)),
+                0x09 => Ok(Value::Boolean(true)),
+                _ => Err(IcnsError::InvalidPlist(format!(
+                    "Unsupported object {:02X}",
+                    marker
+                ))),
+            },
+            0x1 => {
+                let size = 1 << (marker & 0x0F);
+                let value = read_uint(self.bytes(offset + 1, size)?);
+
+                // 1, 2 and 4 byte integers are unsigned, 8 byte integers are signed
+                Ok(Value::Integer(value as i64))
+            }
+            0x2 => match marker & 0x0F {
+                2 => {
+                    let bytes = self.bytes(offset + 1, 4)?;
+                    let value = f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+                    Ok(Value::Real(value as f64))
+                }
+                _ => {
+                    let bytes = self.bytes(offset + 1, 8)?;
+                    let mut value = [0x00; 8];
+                    value.copy_from_slice(bytes);
+
+                    Ok(Value::Real(f64::from_be_bytes(value)))
+                }
+            },
+            0x3 => {
+                let bytes = self.bytes(offset + 1, 8)?;
+                let mut value = [0x00; 8];
+                value.copy_from_slice(bytes);
+
+                // Seconds since 2001-01-01
+                Ok(Value::String(f64::from_be_bytes(value).to_string()))
+            }
+            0x4 => {
+                let (length, start) = self.length(marker, offset)?;
+
+                Ok(Value::Data(self.bytes(start, length)?.to_vec()))
+            }
+            0x5 => {
+                let (length, start) = self.length(marker, offset)?;
+
+                Ok(Value::String(
+                    String::from_utf8_lossy(self.bytes(start, length)?).to_string(),
+                ))
+            }
+            0x6 => {
+                let (length, start) = self.length(marker, offset)?;
+                let units = self
+                    .bytes(start, checked(length.checked_mul(2))?)?
+                    .chunks(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect::<Vec<_>>();
+
+                Ok(Value::String(String::from_utf16_lossy(&units)))
+            }
+            0xA => {
+                let (length, start) = self.length(marker, offset)?;
+                let values = self
+                    .references(start, length)?
+                    .into_iter()
+                    .map(|reference| self.object(reference, path))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(Value::Array(values))
+            }
+            0xD => {
+                let (length, start) = self.length(marker, offset)?;
+                let keys = self.references(start, length)?;
+                let values_start = length
+                    .checked_mul(self.reference_size)
+                    .and_then(|size| start.checked_add(size));
+                let values = self.references(checked(values_start)?, length)?;
+
+                let mut dictionary = Dictionary::new();
+                for (key, value) in keys.into_iter().zip(values) {
+                    let key = match self.object(key, path)? {
+                        Value::String(key) => key,
+                        _ => {
+                            return Err(IcnsError::InvalidPlist(
+                                "Dictionary keys must be strings".to_string(),
+                            ))
+                        }
+                    };
+
+                    dictionary.insert(key, self.object(value, path)?);
+                }
+
+                Ok(Value::Dictionary(dictionary))
+            }
+            _ => Err(IcnsError::InvalidPlist(format!(
+                "Unsupported object {:02X}",
+                marker
+            ))),
+        }
+    }
+}
+
+/// Returns the result of a checked offset calculation or an error if it overflowed
+fn checked(offset: Option<usize>) -> Result<usize, IcnsError> {
+    offset.ok_or_else(|| IcnsError::InvalidPlist("Offset out of range".to_string()))
+}
+
+/// Reads a big endian unsigned integer of up to 8 bytes
+fn read_uint(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| value << 8 | byte as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary() -> Value {
+        let mut values = Dictionary::new();
+        values.insert("name".to_string(), Value::String("<App> & Co".to_string()));
+        values.insert("build".to_string(), Value::Integer(-42));
+        values.insert("scale".to_string(), Value::Real(1.5));
+        values.insert("beta".to_string(), Value::Boolean(true));
+        values.insert(
+            "hash".to_string(),
+            Value::Data(vec![0x00, 0x01, 0xFE, 0xFF]),
+        );
+        values.insert(
+            "tags".to_string(),
+            Value::Array(vec![Value::String("a".to_string()), Value::Array(vec![])]),
+        );
+
+        Value::Dictionary(values)
+    }
+
+    #[test]
+    fn xml_round_trip() {
+        let value = dictionary();
+        let xml = value.to_xml();
+
+        assert!(xml.contains("<string>&lt;App&gt; &amp; Co</string>"));
+        assert!(xml.contains("<data>AAH+/w==</data>"));
+        assert_eq!(Value::parse(xml.as_bytes()).unwrap(), value);
+    }
+
+    #[test]
+    fn parse_binary() {
+        // { "name": "Icon", "size": 512 }
+        let data = [
+            0x62, 0x70, 0x6C, 0x69, 0x73, 0x74, 0x30, 0x30, // bplist00
+            0xD2, 0x01, 0x02, 0x03, 0x04, // Dictionary with 2 entries
+            0x54, 0x6E, 0x61, 0x6D, 0x65, // "name"
+            0x54, 0x73, 0x69, 0x7A, 0x65, // "size"
+            0x54, 0x49, 0x63, 0x6F, 0x6E, // "Icon"
+            0x11, 0x02, 0x00, // 512
+            0x08, 0x0D, 0x12, 0x17, 0x1C, // Offset table
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, // Trailer
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, // Object count
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Root object
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F, // Offset table offset
+        ];
+
+        let value = Value::parse(&data).unwrap();
+        let dictionary = value.as_dictionary().unwrap();
+
+        assert_eq!(dictionary["name"], Value::String("Icon".to_string()));
+        assert_eq!(dictionary["size"], Value::Integer(512));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Value::parse(b"<plist><dict><key>a</key>").is_err());
+        assert!(Value::parse(b"bplist00").is_err());
+
+        // Nested too deep
+        let xml = format!(
+            "<plist>{}{}</plist>",
+            "<array>".repeat(100),
+            "</array>".repeat(100)
+        );
+        assert!(matches!(
+            Value::parse(xml.as_bytes()),
+            Err(IcnsError::InvalidPlist(_))
+        ));
+    }
+
+    #[test]
+    fn parse_binary_invalid_trailer() {
+        let mut data = BINARY_MAGIC.to_vec();
+        data.extend_from_slice(&[0x00; 8]);
+
+        let trailer = |offset_size, reference_size, count: u64, table: u64| {
+            let mut data = data.clone();
+            data.extend_from_slice(&[0, 0, 0, 0, 0, 0, offset_size, reference_size]);
+            data.extend_from_slice(&count.to_be_bytes());
+            data.extend_from_slice(&0u64.to_be_bytes());
+            data.extend_from_slice(&table.to_be_bytes());
+
+            data
+        };
+
+        for data in [
+            // Reference size of 0 and above 8
+            trailer(1, 0, 1, 8),
+            trailer(1, 9, 1, 8),
+            // Overflowing offset table
+            trailer(8, 1, u64::MAX / 4, 8),
+            trailer(1, 1, 1, u64::MAX),
+        ] {
+            assert!(matches!(
+                Value::parse(&data),
+                Err(IcnsError::InvalidPlist(_))
+            ));
+        }
+
+        // An array with more references than fit in memory
+        let mut data = BINARY_MAGIC.to_vec();
+        data.extend_from_slice(&[0xAF, 0x13]);
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        data.push(0x08); // Offset table
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 8]);
+        data.extend_from_slice(&1u64.to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(&18u64.to_be_bytes());
+
+        assert!(matches!(
+            Value::parse(&data),
+            Err(IcnsError::InvalidPlist(_))
+        ));
+    }
+
+    /// A binary plist of arrays with 1 byte references, the object
+    /// count is the number of arrays
+    fn binary_arrays(arrays: &[&[u8]]) -> Vec<u8> {
+        let mut data = BINARY_MAGIC.to_vec();
+        let mut offsets = Vec::new();
+        for references in arrays {
+            offsets.push(data.len() as u8);
+            data.push(0xA0 | references.len() as u8);
+            data.extend_from_slice(references);
+        }
+        // The last object is `true`
+        offsets.push(data.len() as u8);
+        data.push(0x09);
+
+        let table = data.len() as u64;
+        data.extend_from_slice(&offsets);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+        data.extend_from_slice(&(offsets.len() as u64).to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(&table.to_be_bytes());
+
+        data
+    }
+
+    #[test]
+    fn parse_binary_shared_references() {
+        // Shared objects are decoded where they are referenced
+        let value = Value::parse(&binary_arrays(&[&[1, 2], &[2, 2]])).unwrap();
+        let inner = Value::Array(vec![Value::Boolean(true), Value::Boolean(true)]);
+        assert_eq!(value, Value::Array(vec![inner, Value::Boolean(true)]));
+
+        // Every array references the next one twice, decoding it
+        // completely would take 2^40 steps
+        let references = (1..=40).map(|i| [i, i]).collect::<Vec<_>>();
+        let arrays = references.iter().map(|r| &r[..]).collect::<Vec<_>>();
+        assert!(matches!(
+            Value::parse(&binary_arrays(&arrays)),
+            Err(IcnsError::InvalidPlist(_))
+        ));
+
+        // Reference cycle
+        assert!(matches!(
+            Value::parse(&binary_arrays(&[&[1], &[0]])),
+            Err(IcnsError::InvalidPlist(message)) if message.contains("references itself")
+        ));
+    }
+}
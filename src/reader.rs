@@ -0,0 +1,419 @@
+use std::sync::OnceLock;
+
+use crate::{
+    encode::{IcnsDataEntry, DARK, MAGIC, TOC},
+    error::IcnsError,
+    formats::IconFormats,
+    parser::ImageParser,
+};
+
+use image::DynamicImage;
+
+/// ## IcnsEntryRef
+/// An entry of an ICNS file borrowed from the file data.
+/// The `offset` is the position of the 8 byte header in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcnsEntryRef<'a> {
+    pub os_type: [u8; 4],
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+impl<'a> IcnsEntryRef<'a> {
+    /// ## Format
+    /// Returns the icon format of the entry, if it is known.
+    pub fn format(&self) -> Option<IconFormats> {
+        IconFormats::from_bytes(self.os_type)
+    }
+
+    /// ## Copying the entry
+    /// Copies the data into an owned `IcnsDataEntry`.
+    pub fn to_entry(&self) -> IcnsDataEntry {
+        IcnsDataEntry::new(self.os_type, self.data.into())
+    }
+}
+
+/// ## IcnsReader
+/// A lazy reader over the bytes of an ICNS file (e.g. a memory-mapped file).
+/// Only the header and the table of contents are read up front: the
+/// position of every entry is computed from the records of the table of
+/// contents and an entry header is only checked when the entry is
+/// accessed. If the table of contents is missing or doesn't match the
+/// file, the entry headers are scanned instead. The entries borrow the
+/// file data and the pixels are only decoded by `.image()`.
+///
+/// ```no_run
+/// use icns_rs::{reader::IcnsReader, IconFormats};
+///
+/// // Any `&[u8]` works, e.g. a `memmap2::Mmap`
+/// let data = std::fs::read("example.icns").unwrap();
+/// let reader = IcnsReader::new(&data).unwrap();
+///
+/// for entry in reader.entries() {
+///     println!("{:?}: {} bytes", entry.format(), entry.data.len());
+/// }
+///
+/// let image = reader.image(IconFormats::IC07).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct IcnsReader<'a> {
+    data: &'a [u8],
+    /// The entries listed in the table of contents
+    listed: Option<Entries<'a>>,
+    /// The entries found by walking the entry headers
+    scanned: OnceLock<Entries<'a>>,
+}
+
+/// The entries of a file without the table of contents
+#[derive(Debug, Clone, Default)]
+struct Entries<'a> {
+    entries: Vec<IcnsEntryRef<'a>>,
+    dark: Option<IcnsEntryRef<'a>>,
+}
+
+impl<'a> Entries<'a> {
+    fn push(&mut self, entry: IcnsEntryRef<'a>) {
+        match entry.os_type {
+            TOC => {}
+            DARK => self.dark = Some(entry),
+            _ => self.entries.push(entry),
+        }
+    }
+}
+
+impl<'a> IcnsReader<'a> {
+    /// ## New
+    /// Reads the header and the table of contents of an ICNS file.
+    /// The magic bytes and the total length are validated like
+    /// `IconFamily::parse` does. Without a table of contents that
+    /// matches the file, every entry header is read.
+    pub fn new(data: &'a [u8]) -> Result<Self, IcnsError> {
+        Self::read(data, false)
+    }
+
+    /// Reads a family, `nested` is set for the nested dark mode family
+    /// which can't contain another one
+    fn read(data: &'a [u8], nested: bool) -> Result<Self, IcnsError> {
+        if data.len() < 8 {
+            return Err(IcnsError::TruncatedData { offset: data.len() });
+        }
+
+        if data[0..4] != MAGIC {
+            return Err(IcnsError::BadMagic([data[0], data[1], data[2], data[3]]));
+        }
+
+        let total_size = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        if total_size != data.len() {
+            return Err(IcnsError::LengthMismatch {
+                expected: total_size,
+                actual: data.len(),
+            });
+        }
+
+        let reader = Self {
+            data,
+            listed: Self::read_contents_table(data),
+            scanned: OnceLock::new(),
+        };
+
+        // Without a table of contents the headers are read right away,
+        // so a truncated file is an error like in `IconFamily::parse`
+        let dark = match &reader.listed {
+            Some(listed) => listed.dark,
+            None => {
+                let (entries, truncated) = Self::scan(data);
+                if let Some(offset) = truncated {
+                    return Err(IcnsError::TruncatedData { offset });
+                }
+
+                let dark = entries.dark;
+                let _ = reader.scanned.set(entries);
+
+                dark
+            }
+        };
+
+        match dark {
+            Some(dark) if nested => Err(IcnsError::NestedDarkFamily {
+                offset: dark.offset,
+            }),
+            _ => Ok(reader),
+        }
+    }
+
+    /// Computes the entries from the records of the table of contents
+    /// without reading their headers. The records must cover the whole
+    /// file, otherwise `None` is returned.
+    fn read_contents_table(data: &'a [u8]) -> Option<Entries<'a>> {
+        let toc = Self::entry_at(data, 8)?;
+        if toc.os_type != TOC || toc.data.len() % 8 != 0 {
+            return None;
+        }
+
+        let mut entries = Entries::default();
+        let mut offset = 8 + 8 + toc.data.len();
+
+        for record in toc.data.chunks_exact(8) {
+            let length = u32::from_be_bytes([record[4], record[5], record[6], record[7]]) as usize;
+
+            // The length includes the 8 byte header
+            if length < 8 {
+                return None;
+            }
+
+            entries.push(IcnsEntryRef {
+                os_type: [record[0], record[1], record[2], record[3]],
+                offset,
+                data: data.get(offset + 8..offset.checked_add(length)?)?,
+            });
+            offset += length;
+        }
+
+        if offset != data.len() {
+            return None;
+        }
+
+        Some(entries)
+    }
+
+    /// Walks every entry header of the file. Returns the entries before
+    /// the first truncated entry and its offset.
+    fn scan(data: &'a [u8]) -> (Entries<'a>, Option<usize>) {
+        let mut entries = Entries::default();
+
+        let mut offset = 8;
+        while offset < data.len() {
+            let entry = match Self::entry_at(data, offset) {
+                Some(entry) => entry,
+                None => return (entries, Some(offset)),
+            };
+
+            offset += 8 + entry.data.len();
+            entries.push(entry);
+        }
+
+        (entries, None)
+    }
+
+    /// Reads the entry at an offset, if its header and data are complete
+    fn entry_at(data: &'a [u8], offset: usize) -> Option<IcnsEntryRef<'a>> {
+        let header = data.get(offset..offset.checked_add(8)?)?;
+        let length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+
+        // The length includes the 8 byte header
+        if length < 8 {
+            return None;
+        }
+
+        Some(IcnsEntryRef {
+            os_type: [header[0], header[1], header[2], header[3]],
+            offset,
+            data: data.get(offset + 8..offset.checked_add(length)?)?,
+        })
+    }
+
+    /// Checks the header of a listed entry
+    fn has_header(&self, entry: &IcnsEntryRef<'a>) -> bool {
+        Self::entry_at(self.data, entry.offset).is_some_and(|found| {
+            found.os_type == entry.os_type && found.data.len() == entry.data.len()
+        })
+    }
+
+    /// Returns the scanned entries, the file is only scanned once
+    /// For a truncated file these are the entries before it
+    fn scanned(&self) -> &Entries<'a> {
+        self.scanned.get_or_init(|| Self::scan(self.data).0)
+    }
+
+    /// ## Getting the entries
+    /// Returns the entries in file order, without the table of
+    /// contents and the nested dark mode family.
+    /// This checks the header of every entry.
+    pub fn entries(&self) -> &[IcnsEntryRef<'a>] {
+        match &self.listed {
+            Some(listed) if listed.entries.iter().all(|entry| self.has_header(entry)) => {
+                &listed.entries
+            }
+            _ => &self.scanned().entries,
+        }
+    }
+
+    /// ## Getting an entry
+    /// Returns the first entry with the OSType of the format.
+    /// Only the header of this entry is checked. The file is only
+    /// scanned if the table of contents doesn't list the entry or
+    /// the header doesn't match it.
+    pub fn get(&self, format: IconFormats) -> Option<&IcnsEntryRef<'a>> {
+        let os_type = format.get_bytes();
+        let find = |entries: &Entries<'a>| {
+            entries
+                .entries
+                .iter()
+                .position(|entry| entry.os_type == os_type)
+        };
+
+        if let Some(listed) = &self.listed {
+            match find(listed) {
+                Some(index) if self.has_header(&listed.entries[index]) => {
+                    return Some(&listed.entries[index]);
+                }
+                // Missing or the table of contents doesn't match the file
+                _ => {}
+            }
+        }
+
+        let scanned = self.scanned();
+        find(scanned).map(|index| &scanned.entries[index])
+    }
+
+    /// ## Decoding an image
+    /// Decodes the entry of the given format as an image.
+    /// Only this entry (and its mask) is copied and decoded.
+    /// See `IconFamily::image` for more information.
+    pub fn image(&self, format: IconFormats) -> Result<DynamicImage, IcnsError> {
+        let entry = match self.get(format) {
+            Some(entry) => entry.to_entry(),
+            None => return Err(IcnsError::MissingEntry(format)),
+        };
+
+        let mut parser = ImageParser::new();
+        parser.format(format).data(entry);
+
+        if let Some(mask) = format.get_mask() {
+            parser.mask(self.get(mask).map(|mask| mask.to_entry()));
+
+            return Ok(DynamicImage::ImageRgba8(parser.parse()?.to_rgba8()));
+        }
+
+        parser.parse()
+    }
+
+    /// ## Reading the dark mode family
+    /// Reads the nested dark mode family, if there is one.
+    /// Like `IconFamily::parse`, a dark mode family can't contain
+    /// another one.
+    pub fn dark(&self) -> Result<Option<IcnsReader<'a>>, IcnsError> {
+        let dark = match self.listed.as_ref().and_then(|listed| listed.dark) {
+            Some(dark) if self.has_header(&dark) => Some(dark),
+            _ => self.scanned().dark,
+        };
+
+        dark.map(|dark| IcnsReader::read(dark.data, true))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::IconFamily;
+
+    fn family() -> IconFamily {
+        let mut dark = IconFamily::new();
+        dark.add_data(IcnsDataEntry::new(*b"ic07", vec![0x04].into_boxed_slice()));
+
+        let mut family = IconFamily::new();
+        family
+            .add_data(IcnsDataEntry::new(
+                *b"ic07",
+                vec![0x01, 0x02].into_boxed_slice(),
+            ))
+            .add_data(IcnsDataEntry::new(*b"ic08", vec![0x03].into_boxed_slice()));
+        family.set_dark(Some(dark));
+
+        family
+    }
+
+    #[test]
+    fn read_with_contents_table() {
+        let data = family().build();
+        let reader = IcnsReader::new(&data).unwrap();
+
+        assert_eq!(reader.entries().len(), 2);
+
+        let entry = reader.get(IconFormats::IC08).unwrap();
+        assert_eq!(entry.data, &[0x03]);
+        assert_eq!(entry.offset, 8 + 32 + 10);
+        assert_eq!(entry.format(), Some(IconFormats::IC08));
+
+        // The entries are found from the table of contents alone
+        assert!(reader.scanned.get().is_none());
+
+        let dark = reader.dark().unwrap().unwrap();
+        assert_eq!(dark.get(IconFormats::IC07).unwrap().data, &[0x04]);
+        assert!(dark.dark().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_without_contents_table() {
+        let data = family().build();
+
+        // Drop the TOC (8 + 24 bytes) and fix the total length
+        let mut stripped = data[..8].to_vec();
+        stripped.extend_from_slice(&data[40..]);
+        let length = stripped.len() as u32;
+        stripped[4..8].copy_from_slice(&length.to_be_bytes());
+
+        let reader = IcnsReader::new(&stripped).unwrap();
+        assert_eq!(reader.entries().len(), 2);
+        assert_eq!(reader.get(IconFormats::IC07).unwrap().data, &[0x01, 0x02]);
+
+        // A TOC that doesn't match the entries is ignored
+        let mut wrong = data.to_vec();
+        wrong[8 + 8 + 7] = 0xFF;
+        let reader = IcnsReader::new(&wrong).unwrap();
+        assert_eq!(reader.entries().len(), 2);
+
+        // A TOC that covers the file but lists the wrong OSType
+        let mut wrong = data.to_vec();
+        wrong[8 + 8 + 3] = b'9';
+        let reader = IcnsReader::new(&wrong).unwrap();
+        assert!(reader.get(IconFormats::IC09).is_none());
+        assert_eq!(reader.get(IconFormats::IC07).unwrap().data, &[0x01, 0x02]);
+        assert_eq!(reader.entries()[0].os_type, *b"ic07");
+
+        // A truncated entry is an error
+        let mut truncated = stripped.clone();
+        truncated[8 + 7] = 0xFF;
+        assert!(matches!(
+            IcnsReader::new(&truncated),
+            Err(IcnsError::TruncatedData { offset: 8 })
+        ));
+    }
+
+    #[test]
+    fn read_nested_dark_family() {
+        let mut dark = family();
+        dark.set_dark(Some(IconFamily::new()));
+        let mut family = IconFamily::new();
+        family.set_dark(Some(dark));
+
+        let data = family.build();
+        let reader = IcnsReader::new(&data).unwrap();
+
+        assert!(matches!(
+            reader.dark(),
+            Err(IcnsError::NestedDarkFamily { .. })
+        ));
+        assert!(matches!(
+            IconFamily::parse(&data),
+            Err(IcnsError::NestedDarkFamily { .. })
+        ));
+    }
+
+    #[test]
+    fn read_example_file() {
+        let data = include_bytes!("../example.icns");
+        let reader = IcnsReader::new(data).unwrap();
+        let family = IconFamily::parse(data).unwrap();
+
+        assert_eq!(reader.entries().len(), family.data.len());
+        for (entry, owned) in reader.entries().iter().zip(&family.data) {
+            assert_eq!(entry.to_entry(), *owned);
+        }
+
+        for (format, image) in family.images().unwrap() {
+            assert_eq!(reader.image(format).unwrap(), image);
+        }
+    }
+}
@@ -0,0 +1,467 @@
+use std::{collections::HashSet, fmt, io::Cursor};
+
+use crate::{
+    encode::{IconFamily, DARK, INFO, MAGIC, NAME, TOC, VERSION},
+    error::IcnsError,
+    formats::{FileFormat, IconFormats},
+    parser::{ARGB_MAGIC, PNG_MAGIC},
+};
+
+/// # Diagnostics
+/// The problems found by `IconFamily::validate` and `validate_bytes`.
+/// Files with these problems may still be readable, but Finder and
+/// other Apple tools may show the wrong image or reject them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Diagnostic {
+    /// The length in the file header doesn't match the file
+    LengthMismatch { declared: usize, actual: usize },
+    /// The record of the table of contents at the index doesn't match
+    /// the entry at the same position (OSType and length)
+    ContentsTableMismatch {
+        index: usize,
+        listed: Option<([u8; 4], u32)>,
+        found: Option<([u8; 4], u32)>,
+    },
+    /// The OSType is stored more than once
+    DuplicateEntry([u8; 4]),
+    /// The PNG image does not have the dimensions of its icon type
+    InvalidDimensions {
+        os_type: [u8; 4],
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// The PNG header of the entry can't be read
+    InvalidPng([u8; 4]),
+    /// The 8-bit mask doesn't have one byte per pixel
+    InvalidMaskLength {
+        os_type: [u8; 4],
+        expected: usize,
+        actual: usize,
+    },
+    /// The ARGB entry doesn't start with the `ARGB` header (or a PNG)
+    MissingArgbHeader([u8; 4]),
+    /// The OSType is not a known icon type or metadata entry
+    UnknownEntry([u8; 4]),
+    /// A problem of the nested dark mode family
+    Dark(Box<Diagnostic>),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::LengthMismatch { declared, actual } => write!(
+                f,
+                "Declared length is {} bytes, but the file has {}",
+                declared, actual
+            ),
+            Diagnostic::ContentsTableMismatch {
+                index,
+                listed,
+                found,
+            } => write!(
+                f,
+                "Table of contents record {} lists {}, but the entry is {}",
+                index,
+                describe(listed),
+                describe(found)
+            ),
+            Diagnostic::DuplicateEntry(os_type) => {
+                write!(f, "{} is stored more than once", name(os_type))
+            }
+            Diagnostic::InvalidDimensions {
+                os_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} is {}x{}, expected {}x{}",
+                name(os_type),
+                actual.0,
+                actual.1,
+                expected.0,
+                expected.1
+            ),
+            Diagnostic::InvalidPng(os_type) => write!(f, "{} is not a valid PNG", name(os_type)),
+            Diagnostic::InvalidMaskLength {
+                os_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} has {} bytes, expected {}",
+                name(os_type),
+                actual,
+                expected
+            ),
+            Diagnostic::MissingArgbHeader(os_type) => {
+                write!(f, "{} is missing the ARGB header", name(os_type))
+            }
+            Diagnostic::UnknownEntry(os_type) => write!(f, "{} is unknown", name(os_type)),
+            Diagnostic::Dark(diagnostic) => write!(f, "Dark mode family: {}", diagnostic),
+        }
+    }
+}
+
+/// Returns the OSType as text for messages
+fn name(os_type: &[u8; 4]) -> String {
+    format!("\"{}\"", String::from_utf8_lossy(os_type).escape_debug())
+}
+
+/// Describes a table of contents record for messages
+fn describe(record: &Option<([u8; 4], u32)>) -> String {
+    match record {
+        Some((os_type, length)) => format!("{} ({} bytes)", name(os_type), length),
+        None => "missing".to_string(),
+    }
+}
+
+impl IconFamily {
+    /// ## Validating the entries
+    /// Checks the entries of the family (and the nested dark mode family):
+    /// - Every OSType is only stored once
+    /// - PNG images have the dimensions of their icon type
+    /// - 8-bit masks have one byte per pixel
+    /// - ARGB entries start with the `ARGB` header (or are PNGs)
+    /// - Every OSType is a known icon type or metadata entry
+    ///
+    /// The header and the table of contents are not part of an
+    /// `IconFamily`, see `validate_bytes` to check them as well.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut seen = HashSet::new();
+
+        for entry in &self.data {
+            if !seen.insert(entry.os_type) {
+                diagnostics.push(Diagnostic::DuplicateEntry(entry.os_type));
+            }
+
+            let format = match IconFormats::from_bytes(entry.os_type) {
+                Some(format) => format,
+                None => {
+                    if ![INFO, VERSION, NAME].contains(&entry.os_type) {
+                        diagnostics.push(Diagnostic::UnknownEntry(entry.os_type));
+                    }
+                    continue;
+                }
+            };
+
+            let (width, height) = format.get_dimensions();
+
+            if entry.data.starts_with(&PNG_MAGIC) {
+                let dimensions = image::io::Reader::with_format(
+                    Cursor::new(&entry.data),
+                    image::ImageFormat::Png,
+                )
+                .into_dimensions();
+
+                match dimensions {
+                    Ok(actual) if actual != (width as u32, height as u32) => {
+                        diagnostics.push(Diagnostic::InvalidDimensions {
+                            os_type: entry.os_type,
+                            expected: (width as u32, height as u32),
+                            actual,
+                        })
+                    }
+                    Ok(_) => {}
+                    Err(_) => diagnostics.push(Diagnostic::InvalidPng(entry.os_type)),
+                }
+
+                continue;
+            }
+
+            match format.get_format() {
+                FileFormat::MASK if entry.data.len() != width * height => {
+                    diagnostics.push(Diagnostic::InvalidMaskLength {
+                        os_type: entry.os_type,
+                        expected: width * height,
+                        actual: entry.data.len(),
+                    })
+                }
+                FileFormat::ARGB if !entry.data.starts_with(&ARGB_MAGIC) => {
+                    diagnostics.push(Diagnostic::MissingArgbHeader(entry.os_type))
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(dark) = &self.dark {
+            diagnostics.extend(
+                dark.validate()
+                    .into_iter()
+                    .map(|diagnostic| Diagnostic::Dark(Box::new(diagnostic))),
+            );
+        }
+
+        diagnostics
+    }
+}
+
+/// ## Validating a file
+/// Checks the header and the table of contents of an ICNS file and
+/// then validates its entries (see `IconFamily::validate`).
+/// Returns an error if the file can't be read at all, e.g. if the
+/// magic bytes are wrong or an entry is truncated.
+///
+/// ```no_run
+/// use icns_rs::validate::validate_bytes;
+///
+/// let data = std::fs::read("icon.icns").unwrap();
+///
+/// for diagnostic in validate_bytes(&data).unwrap() {
+///     println!("{}", diagnostic);
+/// }
+/// ```
+pub fn validate_bytes(data: &[u8]) -> Result<Vec<Diagnostic>, IcnsError> {
+    let mut diagnostics = validate_structure(data, false)?;
+
+    // The entries are validated even if the declared lengths are wrong
+    let mut fixed = data.to_vec();
+    fix_lengths(&mut fixed);
+
+    diagnostics.extend(IconFamily::parse(&fixed)?.validate());
+
+    Ok(diagnostics)
+}
+
+/// Sets the declared length of a file and of its nested dark mode
+/// family to their actual length. The records must already be checked
+/// by `validate_structure`.
+fn fix_lengths(data: &mut [u8]) {
+    let length = data.len() as u32;
+    data[4..8].copy_from_slice(&length.to_be_bytes());
+
+    let mut i = 8;
+    while i < data.len() {
+        let length =
+            u32::from_be_bytes([data[i + 4], data[i + 5], data[i + 6], data[i + 7]]) as usize;
+
+        if data[i..i + 4] == DARK {
+            fix_lengths(&mut data[i + 8..i + length]);
+        }
+
+        i += length;
+    }
+}
+
+/// Checks the declared length and the table of contents of a file
+/// and of its nested dark mode family, `dark` is set for the nested
+/// dark mode family which can't contain another one
+fn validate_structure(data: &[u8], dark: bool) -> Result<Vec<Diagnostic>, IcnsError> {
+    if data.len() < 8 {
+        return Err(IcnsError::TruncatedData { offset: data.len() });
+    }
+
+    if data[0..4] != MAGIC {
+        return Err(IcnsError::BadMagic([data[0], data[1], data[2], data[3]]));
+    }
+
+    let mut diagnostics = Vec::new();
+
+    let declared = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    if declared != data.len() {
+        diagnostics.push(Diagnostic::LengthMismatch {
+            declared,
+            actual: data.len(),
+        });
+    }
+
+    // Walk every OSType / length record
+    let mut contents_table = None;
+    let mut records = Vec::new();
+
+    let mut i = 8;
+    while i < data.len() {
+        if i + 8 > data.len() {
+            return Err(IcnsError::TruncatedData { offset: i });
+        }
+
+        let os_type = [data[i], data[i + 1], data[i + 2], data[i + 3]];
+        let length = u32::from_be_bytes([data[i + 4], data[i + 5], data[i + 6], data[i + 7]]);
+
+        // The length includes the 8 byte header
+        if length < 8 || i + length as usize > data.len() {
+            return Err(IcnsError::TruncatedData { offset: i });
+        }
+
+        let entry = &data[i + 8..i + length as usize];
+        match os_type {
+            TOC => contents_table = Some(entry),
+            DARK if dark => return Err(IcnsError::NestedDarkFamily { offset: i }),
+            DARK => diagnostics.extend(
+                validate_structure(entry, true)?
+                    .into_iter()
+                    .map(|diagnostic| Diagnostic::Dark(Box::new(diagnostic))),
+            ),
+            _ => {}
+        }
+        if os_type != TOC {
+            records.push((os_type, length));
+        }
+
+        i += length as usize;
+    }
+
+    // The table of contents is optional, but must match the entries
+    if let Some(contents_table) = contents_table {
+        let listed = contents_table
+            .chunks_exact(8)
+            .map(|record| {
+                (
+                    [record[0], record[1], record[2], record[3]],
+                    u32::from_be_bytes([record[4], record[5], record[6], record[7]]),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for index in 0..listed.len().max(records.len()) {
+            let listed = listed.get(index).copied();
+            let found = records.get(index).copied();
+
+            if listed != found {
+                diagnostics.push(Diagnostic::ContentsTableMismatch {
+                    index,
+                    listed,
+                    found,
+                });
+            }
+        }
+    }
+
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode::IcnsDataEntry, IcnsEncoder};
+
+    use image::DynamicImage;
+
+    #[test]
+    fn validate_valid_family() {
+        let mut encoder = IcnsEncoder::new();
+        encoder
+            .data(DynamicImage::new_rgba8(32, 32))
+            .dark_data(Some(DynamicImage::new_rgba8(32, 32)))
+            .formats(vec![
+                IconFormats::IS32,
+                IconFormats::S8MK,
+                IconFormats::IC04,
+                IconFormats::IC11,
+            ])
+            .version(Some(1.0));
+        let data = encoder.build().unwrap();
+
+        assert_eq!(validate_bytes(&data).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn validate_invalid_entries() {
+        let png = IcnsEncoder::new()
+            .data(DynamicImage::new_rgba8(32, 32))
+            .formats(vec![IconFormats::IC11])
+            .build()
+            .unwrap();
+        let png = IconFamily::parse(&png).unwrap().data[0].data.clone();
+
+        let mut dark = IconFamily::new();
+        dark.add_data(IcnsDataEntry::new(*b"xxxx", vec![].into_boxed_slice()));
+
+        let mut family = IconFamily::new();
+        family
+            .add_data(IcnsDataEntry::new(*b"ic07", png.clone()))
+            .add_data(IcnsDataEntry::new(*b"ic11", png.clone()))
+            .add_data(IcnsDataEntry::new(*b"ic11", png))
+            .add_data(IcnsDataEntry::new(
+                *b"s8mk",
+                vec![0xFF; 16].into_boxed_slice(),
+            ))
+            .add_data(IcnsDataEntry::new(
+                *b"ic04",
+                vec![0x00; 4].into_boxed_slice(),
+            ))
+            .add_data(IcnsDataEntry::new(
+                *b"name",
+                b"Icon".to_vec().into_boxed_slice(),
+            ));
+        family.set_dark(Some(dark));
+
+        assert_eq!(
+            family.validate(),
+            vec![
+                Diagnostic::InvalidDimensions {
+                    os_type: *b"ic07",
+                    expected: (128, 128),
+                    actual: (32, 32),
+                },
+                Diagnostic::DuplicateEntry(*b"ic11"),
+                Diagnostic::InvalidMaskLength {
+                    os_type: *b"s8mk",
+                    expected: 256,
+                    actual: 16,
+                },
+                Diagnostic::MissingArgbHeader(*b"ic04"),
+                Diagnostic::Dark(Box::new(Diagnostic::UnknownEntry(*b"xxxx"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_invalid_structure() {
+        let mut family = IconFamily::new();
+        family
+            .add_data(IcnsDataEntry::new(*b"info", vec![].into_boxed_slice()))
+            .add_data(IcnsDataEntry::new(*b"name", vec![].into_boxed_slice()));
+        let mut data = family.build().to_vec();
+
+        // Wrong total length and a wrong length in the TOC
+        data[7] += 1;
+        data[8 + 8 + 8 + 7] = 0x09;
+
+        assert_eq!(
+            validate_bytes(&data).unwrap(),
+            vec![
+                Diagnostic::LengthMismatch {
+                    declared: data.len() + 1,
+                    actual: data.len(),
+                },
+                Diagnostic::ContentsTableMismatch {
+                    index: 1,
+                    listed: Some((*b"name", 9)),
+                    found: Some((*b"name", 8)),
+                },
+            ]
+        );
+        assert!(matches!(
+            validate_bytes(b"icon\x00\x00\x00\x08"),
+            Err(IcnsError::BadMagic(_))
+        ));
+
+        // Wrong length of the dark mode family
+        let mut family = IconFamily::new();
+        family.set_dark(Some(IconFamily::new()));
+        let mut data = family.build().to_vec();
+        // Header, TOC, dark mode entry header
+        data[8 + 16 + 8 + 7] += 1;
+
+        assert_eq!(
+            validate_bytes(&data).unwrap(),
+            vec![Diagnostic::Dark(Box::new(Diagnostic::LengthMismatch {
+                declared: 17,
+                actual: 16,
+            }))]
+        );
+
+        // A dark mode family inside a dark mode family
+        let mut dark = IconFamily::new();
+        dark.set_dark(Some(IconFamily::new()));
+        let mut family = IconFamily::new();
+        family.set_dark(Some(dark));
+
+        assert!(matches!(
+            validate_bytes(&family.build()),
+            Err(IcnsError::NestedDarkFamily { .. })
+        ));
+    }
+}